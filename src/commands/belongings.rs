@@ -0,0 +1,187 @@
+use anyhow::Error;
+use chrono::{Duration, Utc};
+use itertools::Itertools;
+use poise::serenity_prelude::*;
+
+use crate::{
+    PoiseContext,
+    data::{self, BelongingTarget, StandingBelonging},
+    utilities::{autocomplete_subject, format_date, guild_data, parse_naive_date, parse_weekdays},
+};
+
+fn format_belongings(belongings: &[StandingBelonging]) -> String {
+    if belongings.is_empty() {
+        return "(なし)".into();
+    }
+    belongings
+        .iter()
+        .map(|belonging| format!("- {}", belonging.describe()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD")]
+/// 教科の授業がある日や、決まった曜日に必要な持ち物を追加します。
+pub async fn add_standing_belonging(
+    ctx: PoiseContext<'_>,
+    #[description = "持ち物 (例: 体操服)"] item: String,
+    #[description = "この教科の授業がある日に必要"]
+    #[autocomplete = "autocomplete_subject"]
+    subject: Option<String>,
+    #[description = "この曜日に必要 (例: 水)"] weekday: Option<String>,
+) -> Result<(), Error> {
+    let (guild_id, guild) = guild_data(ctx)?;
+
+    let target = match (subject, weekday) {
+        (Some(subject), None) => {
+            anyhow::ensure!(
+                guild.subjects.lock().unwrap().contains(&subject),
+                "Subject not found: {}",
+                subject
+            );
+            BelongingTarget::Subject(subject)
+        }
+        (None, Some(weekday)) => match parse_weekdays(&weekday)?[..] {
+            [weekday] => BelongingTarget::Weekday(weekday),
+            _ => anyhow::bail!("Specify exactly one weekday: {}", weekday),
+        },
+        _ => anyhow::bail!("Specify either subject or weekday"),
+    };
+    let belonging = StandingBelonging { item, target };
+
+    let description = {
+        let mut belongings = guild.standing_belongings.lock().unwrap();
+        if !belongings.contains(&belonging) {
+            belongings.push(belonging);
+        }
+        format_belongings(&belongings)
+    };
+    data::save(ctx.data(), guild_id)?;
+
+    ctx.send(
+        poise::CreateReply::default().embed(
+            CreateEmbed::default()
+                .title("常備の持ち物を追加しました")
+                .description(description)
+                .color(Color::DARK_BLUE),
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+async fn autocomplete_item<'a>(ctx: PoiseContext<'a>, partial: &'a str) -> Vec<String> {
+    let Ok((_, guild)) = guild_data(ctx) else {
+        return vec![];
+    };
+    guild
+        .standing_belongings
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|belonging| belonging.item.clone())
+        .filter(|item| item.contains(partial))
+        .unique()
+        .take(25)
+        .collect()
+}
+
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD")]
+/// 常備の持ち物を削除します。
+pub async fn remove_standing_belonging(
+    ctx: PoiseContext<'_>,
+    #[description = "削除する持ち物"]
+    #[autocomplete = "autocomplete_item"]
+    item: String,
+) -> Result<(), Error> {
+    let (guild_id, guild) = guild_data(ctx)?;
+
+    let description = {
+        let mut belongings = guild.standing_belongings.lock().unwrap();
+        let len = belongings.len();
+        belongings.retain(|belonging| belonging.item != item);
+        anyhow::ensure!(belongings.len() < len, "Belonging not found");
+        format_belongings(&belongings)
+    };
+    data::save(ctx.data(), guild_id)?;
+
+    ctx.send(
+        poise::CreateReply::default().embed(
+            CreateEmbed::default()
+                .title("常備の持ち物を削除しました")
+                .description(description)
+                .color(Color::DARK_BLUE),
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only)]
+/// 常備の持ち物の一覧を表示します。
+pub async fn list_standing_belongings(ctx: PoiseContext<'_>) -> Result<(), Error> {
+    let (_, guild) = guild_data(ctx)?;
+
+    let description = format_belongings(&guild.standing_belongings.lock().unwrap());
+    ctx.send(
+        poise::CreateReply::default().embed(
+            CreateEmbed::default()
+                .title("常備の持ち物")
+                .description(description)
+                .color(Color::DARK_BLUE),
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only)]
+/// 常備の持ち物を、指定した日だけ不要にします。
+pub async fn skip_belonging(
+    ctx: PoiseContext<'_>,
+    #[description = "不要にする持ち物"]
+    #[autocomplete = "autocomplete_item"]
+    item: String,
+    #[description = "不要にする日 (例: 2025-02-06、省略すると明日)"] date: Option<String>,
+) -> Result<(), Error> {
+    let (guild_id, guild) = guild_data(ctx)?;
+    let today = Utc::now().with_timezone(&guild.timezone()).date_naive();
+
+    let date = match date {
+        Some(date) => parse_naive_date(&date)?,
+        None => today + Duration::days(1),
+    };
+    anyhow::ensure!(
+        guild
+            .standing_belongings
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|belonging| belonging.item == item),
+        "Belonging not found: {}",
+        item
+    );
+
+    {
+        let mut skipped = guild.skipped_belongings.lock().unwrap();
+        // 過ぎた日の記録は不要になる
+        skipped.retain(|(date, _)| today <= *date);
+        skipped.insert((date, item.clone()));
+    }
+    data::save(ctx.data(), guild_id)?;
+
+    ctx.send(
+        poise::CreateReply::default().embed(
+            CreateEmbed::default()
+                .title("持ち物を不要にしました")
+                .description(format!("{}の「{}」", format_date(date), item))
+                .color(Color::DARK_BLUE),
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
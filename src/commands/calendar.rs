@@ -0,0 +1,495 @@
+use anyhow::{Context as _, Error};
+use chrono::{Duration, Utc};
+use poise::serenity_prelude::*;
+use uuid::Uuid;
+
+use crate::{
+    Category, PoiseContext, Subject, Task,
+    data::{self, CalendarFeed, GuildData, ImportRule, TaskFilter},
+    http, ics,
+    interactions::preview_tasks,
+    periodic::ping,
+    utilities::{autocomplete_category, autocomplete_subject, format_date, guild_data, parse_date},
+};
+
+#[poise::command(slash_command, guild_only)]
+/// タスクをカレンダー(.ics)ファイルとして書き出します。
+pub async fn export_ics(
+    ctx: PoiseContext<'_>,
+    #[description = "種類で絞り込む"]
+    #[autocomplete = "autocomplete_category"]
+    category: Option<String>,
+    #[description = "教科で絞り込む"]
+    #[autocomplete = "autocomplete_subject"]
+    subject: Option<String>,
+    #[description = "この日以降のタスクのみ (例: 2025-04-01)"] from: Option<String>,
+    #[description = "この日までのタスクのみ (例: 2025-07-31)"] to: Option<String>,
+    #[description = "過去のタスクも含める"] include_archived: Option<bool>,
+) -> Result<(), Error> {
+    let (guild_id, guild) = guild_data(ctx)?;
+    let tz = guild.timezone();
+    let category = category
+        .map(|category| guild.find_category(&category).context("Unknown category"))
+        .transpose()?;
+
+    let mut filter = TaskFilter {
+        category,
+        subject,
+        from: from
+            .as_deref()
+            .map(|from| parse_date(from, tz))
+            .transpose()?,
+        to: to
+            .as_deref()
+            .map(|to| Ok::<_, Error>(parse_date(to, tz)? + Duration::days(1)))
+            .transpose()?,
+    };
+    if !include_archived.unwrap_or(false) {
+        filter.from = Some(filter.from.map_or(Utc::now(), |from| from.max(Utc::now())));
+    }
+
+    let tasks = ctx
+        .data()
+        .storage
+        .tasks(guild_id)?
+        .into_iter()
+        .filter(|task| filter.matches(task))
+        .collect::<Vec<_>>();
+
+    let name = guild_id
+        .name(ctx)
+        .unwrap_or_else(|| "task-bot-rs".to_string());
+    let calendar = ics::export(&name, &tasks, &guild);
+
+    let mut conditions = vec![];
+    if let Some(category) = &filter.category {
+        conditions.push(format!("種類: {}", guild.category(category).name));
+    }
+    if let Some(subject) = &filter.subject {
+        conditions.push(format!("教科: {}", subject));
+    }
+    if let Some(from) = filter.from {
+        conditions.push(format!(
+            "開始: {}",
+            format_date(from.with_timezone(&tz).date_naive())
+        ));
+    }
+    if let Some(to) = filter.to {
+        conditions.push(format!(
+            "終了: {}",
+            format_date((to - Duration::days(1)).with_timezone(&tz).date_naive())
+        ));
+    }
+
+    ctx.send(
+        poise::CreateReply::default()
+            .embed(
+                CreateEmbed::default()
+                    .title(format!("{}件のタスクを書き出しました", tasks.len()))
+                    .description(conditions.join("\n"))
+                    .color(Color::DARK_GREEN),
+            )
+            .attachment(CreateAttachment::bytes(calendar, "tasks.ics")),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// フィードの購読用URLを返します。
+fn feed_urls(public_url: &str, feed: &CalendarFeed, guild: &GuildData) -> String {
+    let url = format!("{}/calendar/{}.ics", public_url, feed.token);
+    let webcal = match url.split_once("://") {
+        Some((_, rest)) => format!("webcal://{}", rest),
+        None => url.clone(),
+    };
+    let mut conditions = vec![];
+    if let Some(category) = &feed.category {
+        conditions.push(format!("種類: {}", guild.category(category).name));
+    }
+    if let Some(subject) = &feed.subject {
+        conditions.push(format!("教科: {}", subject));
+    }
+    if conditions.is_empty() {
+        conditions.push("すべてのタスク".into());
+    }
+    format!("**{}**\n{}\n{}", conditions.join(", "), webcal, url)
+}
+
+#[poise::command(slash_command, guild_only)]
+/// カレンダーアプリで購読できるURLを発行します。
+pub async fn calendar_feed(
+    ctx: PoiseContext<'_>,
+    #[description = "種類で絞り込む"]
+    #[autocomplete = "autocomplete_category"]
+    category: Option<String>,
+    #[description = "教科で絞り込む"]
+    #[autocomplete = "autocomplete_subject"]
+    subject: Option<String>,
+) -> Result<(), Error> {
+    let (guild_id, guild) = guild_data(ctx)?;
+    let public_url = http::public_url()?;
+    let category = category
+        .map(|category| guild.find_category(&category).context("Unknown category"))
+        .transpose()?;
+
+    let feed = {
+        let mut feeds = guild.calendar_feeds.lock().unwrap();
+        match feeds
+            .iter()
+            .find(|feed| feed.category == category && feed.subject == subject)
+        {
+            Some(feed) => feed.clone(),
+            None => {
+                let feed = CalendarFeed::new(category, subject);
+                feeds.push(feed.clone());
+                feed
+            }
+        }
+    };
+    data::save(ctx.data(), guild_id)?;
+
+    ctx.send(
+        poise::CreateReply::default().ephemeral(true).embed(
+            CreateEmbed::default()
+                .title("カレンダーのURL")
+                .description(format!(
+                    "{}\n\nカレンダーアプリで購読すると、タスクの変更が自動で反映されます。\nURLを知っている人は誰でもタスクを閲覧できるため、共有には注意してください。",
+                    feed_urls(&public_url, &feed, &guild)
+                ))
+                .color(Color::DARK_GREEN),
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD")]
+/// カレンダーのURLをすべて新しいものに変更し、古いURLを無効にします。
+pub async fn rotate_calendar_feeds(ctx: PoiseContext<'_>) -> Result<(), Error> {
+    let (guild_id, guild) = guild_data(ctx)?;
+    let public_url = http::public_url()?;
+
+    let feeds = {
+        let mut feeds = guild.calendar_feeds.lock().unwrap();
+        for feed in feeds.iter_mut() {
+            *feed = CalendarFeed::new(feed.category.clone(), feed.subject.clone());
+        }
+        feeds.clone()
+    };
+    anyhow::ensure!(!feeds.is_empty(), "No calendar feeds");
+    data::save(ctx.data(), guild_id)?;
+
+    ctx.send(
+        poise::CreateReply::default().ephemeral(true).embed(
+            CreateEmbed::default()
+                .title(format!("{}件のURLを変更しました", feeds.len()))
+                .description(
+                    feeds
+                        .iter()
+                        .map(|feed| feed_urls(&public_url, feed, &guild))
+                        .collect::<Vec<_>>()
+                        .join("\n\n"),
+                )
+                .color(Color::DARK_GREEN),
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// VEVENTからタスクを作成し、種類と教科を推測します。
+///
+/// 推測は、このBotが書き出した形式(`【種類】教科 内容`)、取り込みの規則、CATEGORIES、
+/// 教科名を含むかどうかの順に行います。
+fn to_task(event: &ics::Event, guild: &GuildData) -> Option<Task> {
+    let datetime = event.start?;
+    let subjects = guild.subjects.lock().unwrap().clone();
+    let rules = guild.import_rules.lock().unwrap().clone();
+
+    let mut details = event.summary.trim();
+    let mut category = None;
+    let mut subject = None;
+
+    // 絵文字が設定された種類は`絵文字 【種類】`の形式で書き出される
+    if let Some((prefix, rest)) = details.split_once('【')
+        && let Some((label, rest)) = rest.split_once('】')
+        && let Some(c) = guild.find_category(label)
+        && (prefix.is_empty()
+            || guild
+                .category(&c)
+                .emoji
+                .is_some_and(|emoji| prefix == format!("{} ", emoji)))
+    {
+        category = Some(c);
+        details = rest;
+    }
+    if let Some((s, rest)) = subjects.iter().find_map(|s| {
+        details
+            .strip_prefix(s.as_str())
+            .and_then(|rest| rest.strip_prefix(' '))
+            .map(|rest| (s, rest))
+    }) {
+        subject = Some(s.clone());
+        details = rest;
+    }
+
+    let text = format!(
+        "{}\n{}",
+        event.summary,
+        event.description.as_deref().unwrap_or_default()
+    );
+    for rule in rules.iter().filter(|rule| text.contains(&rule.keyword)) {
+        category = category.or(rule.category.clone());
+        subject = subject.or(rule.subject.clone());
+    }
+    category = category.or_else(|| {
+        event
+            .categories
+            .iter()
+            .find_map(|label| guild.find_category(label))
+    });
+    subject = subject.or_else(|| subjects.iter().find(|s| text.contains(*s)).cloned());
+
+    let id = match &event.uid {
+        Some(uid) => uid
+            .strip_suffix("@task-bot-rs")
+            .and_then(|id| id.parse().ok())
+            .unwrap_or_else(|| Uuid::new_v5(&Uuid::NAMESPACE_URL, uid.as_bytes())),
+        None => Uuid::new_v4(),
+    };
+
+    Some(Task {
+        category: category.unwrap_or_else(|| Category("Event".into())),
+        subject: subject.map_or(Subject::Unset, Subject::Set),
+        details: details.to_string(),
+        datetime,
+        id,
+    })
+}
+
+#[poise::command(slash_command, guild_only)]
+/// カレンダー(.ics)ファイルからタスクを一括で追加します。
+pub async fn import_ics(
+    ctx: PoiseContext<'_>,
+    #[description = "カレンダー(.ics)ファイル"] file: Attachment,
+) -> Result<(), Error> {
+    let (guild_id, guild) = guild_data(ctx)?;
+
+    ctx.defer().await?;
+
+    let contents = String::from_utf8(file.download().await?)?;
+    let events = ics::parse(&contents, guild.timezone())?;
+    let existing = ctx.data().storage.tasks(guild_id)?;
+
+    let mut tasks: Vec<Task> = vec![];
+    let mut invalid = 0;
+    let mut duplicates = 0;
+    for event in &events {
+        let Some(task) = to_task(event, &guild) else {
+            invalid += 1;
+            continue;
+        };
+        let is_duplicate = |other: &Task| {
+            other.id == task.id
+                || (other.datetime == task.datetime && other.details == task.details)
+        };
+        if existing.iter().chain(&tasks).any(is_duplicate) {
+            duplicates += 1;
+        } else {
+            tasks.push(task);
+        }
+    }
+    tasks.sort_by_key(|task| task.datetime);
+
+    let mut notes = vec![];
+    if 0 < duplicates {
+        notes.push(format!(
+            "既存のタスクと重複する{}件をスキップしました",
+            duplicates
+        ));
+    }
+    if 0 < invalid {
+        notes.push(format!(
+            "日時がないか読み込めない{}件をスキップしました",
+            invalid
+        ));
+    }
+
+    if tasks.is_empty() {
+        ctx.send(
+            poise::CreateReply::default().embed(
+                CreateEmbed::default()
+                    .title("追加できるタスクがありません")
+                    .description(notes.join("\n"))
+                    .color(Color::RED),
+            ),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let (last_interaction, tasks) = preview_tasks(
+        ctx,
+        None,
+        CreateEmbed::default()
+            .title(format!("{}からタスクを追加します", file.filename))
+            .description(notes.join("\n"))
+            .color(Color::DARK_BLUE),
+        tasks,
+    )
+    .await?;
+
+    let Some(mut tasks) = tasks else {
+        let response = CreateInteractionResponse::UpdateMessage(
+            CreateInteractionResponseMessage::default()
+                .embed(
+                    CreateEmbed::default()
+                        .title("追加をキャンセルしました")
+                        .color(Color::DARK_RED),
+                )
+                .components(vec![]),
+        );
+        last_interaction.create_response(ctx, response).await?;
+        return Ok(());
+    };
+
+    for task in &mut tasks {
+        // 確認している間に同じIDのタスクが追加された場合は、新しいIDを振る
+        if ctx.data().storage.task(guild_id, task.id)?.is_some() {
+            task.id = Uuid::new_v4();
+        }
+        ctx.data().add_task(guild_id, ctx.author().id, task)?;
+    }
+
+    let response = CreateInteractionResponse::UpdateMessage(
+        CreateInteractionResponseMessage::default()
+            .embed(
+                CreateEmbed::default()
+                    .title(format!("{}件のタスクを追加しました", tasks.len()))
+                    .color(Color::DARK_GREEN),
+            )
+            .components(vec![]),
+    );
+    last_interaction.create_response(ctx, response).await?;
+
+    ping::update(&ctx).await?;
+
+    Ok(())
+}
+
+fn format_rules(rules: &[ImportRule], guild: &GuildData) -> String {
+    if rules.is_empty() {
+        return "なし".into();
+    }
+    rules
+        .iter()
+        .map(|rule| {
+            let mut guesses = vec![];
+            if let Some(category) = &rule.category {
+                guesses.push(format!("種類: {}", guild.category(category).name));
+            }
+            if let Some(subject) = &rule.subject {
+                guesses.push(format!("教科: {}", subject));
+            }
+            format!("- 「{}」→ {}", rule.keyword, guesses.join(", "))
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD")]
+/// 取り込み時に種類や教科を推測するキーワードを追加します。
+pub async fn add_import_rule(
+    ctx: PoiseContext<'_>,
+    #[description = "予定の名前や説明に含まれるキーワード"] keyword: String,
+    #[description = "キーワードを含む予定の種類"]
+    #[autocomplete = "autocomplete_category"]
+    category: Option<String>,
+    #[description = "キーワードを含む予定の教科"]
+    #[autocomplete = "autocomplete_subject"]
+    subject: Option<String>,
+) -> Result<(), Error> {
+    let (guild_id, guild) = guild_data(ctx)?;
+    let category = category
+        .map(|category| guild.find_category(&category).context("Unknown category"))
+        .transpose()?;
+
+    anyhow::ensure!(
+        category.is_some() || subject.is_some(),
+        "Specify a category or a subject"
+    );
+
+    let rules = {
+        let mut rules = guild.import_rules.lock().unwrap();
+        rules.retain(|rule| rule.keyword != keyword);
+        rules.push(ImportRule {
+            keyword,
+            category,
+            subject,
+        });
+        rules.clone()
+    };
+    data::save(ctx.data(), guild_id)?;
+
+    ctx.send(
+        poise::CreateReply::default().embed(
+            CreateEmbed::default()
+                .title("取り込みの規則を追加しました")
+                .description(format_rules(&rules, &guild))
+                .color(Color::DARK_GREEN),
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+async fn autocomplete_keyword<'a>(ctx: PoiseContext<'a>, partial: &'a str) -> Vec<String> {
+    let Ok((_, guild)) = guild_data(ctx) else {
+        return vec![];
+    };
+    guild
+        .import_rules
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|rule| rule.keyword.clone())
+        .filter(|keyword| keyword.contains(partial))
+        .take(25)
+        .collect()
+}
+
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD")]
+/// カレンダーの取り込みの規則を削除します。
+pub async fn remove_import_rule(
+    ctx: PoiseContext<'_>,
+    #[description = "削除する規則のキーワード"]
+    #[autocomplete = "autocomplete_keyword"]
+    keyword: String,
+) -> Result<(), Error> {
+    let (guild_id, guild) = guild_data(ctx)?;
+
+    let rules = {
+        let mut rules = guild.import_rules.lock().unwrap();
+        let len = rules.len();
+        rules.retain(|rule| rule.keyword != keyword);
+        anyhow::ensure!(rules.len() < len, "Rule not found");
+        rules.clone()
+    };
+    data::save(ctx.data(), guild_id)?;
+
+    ctx.send(
+        poise::CreateReply::default().embed(
+            CreateEmbed::default()
+                .title("取り込みの規則を削除しました")
+                .description(format_rules(&rules, &guild))
+                .color(Color::DARK_GREEN),
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,224 @@
+use anyhow::{Context as _, Error};
+use poise::serenity_prelude::*;
+
+use crate::{
+    PoiseContext,
+    data::{self, Category, CategoryInfo, GuildData},
+    utilities::{autocomplete_category, guild_data},
+};
+
+fn format_categories(categories: &[CategoryInfo]) -> String {
+    categories
+        .iter()
+        .map(|category| {
+            let mut flags = vec![];
+            if category.warn {
+                flags.push("期限接近通知");
+            }
+            if category.belongings {
+                flags.push("持ち物");
+            }
+            if category.archived {
+                flags.push("アーカイブ済み");
+            }
+            match category.color {
+                Some(color) => format!(
+                    "- {} #{} {}",
+                    category.label(),
+                    color.hex(),
+                    flags.join("、")
+                ),
+                None => format!("- {} {}", category.label(), flags.join("、")),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn parse_color(color: &str) -> Result<Color, Error> {
+    u32::from_str_radix(color.trim_start_matches('#'), 16)
+        .ok()
+        .filter(|_| color.trim_start_matches('#').len() == 6)
+        .map(Color::new)
+        .with_context(|| format!("Invalid color: {}", color))
+}
+
+/// 種類の一覧を変更します。既定の種類のままの場合は、既定の種類を引き継ぎます。
+fn modify_categories(
+    guild: &GuildData,
+    f: impl FnOnce(&mut Vec<CategoryInfo>) -> Result<(), Error>,
+) -> Result<Vec<CategoryInfo>, Error> {
+    let mut categories = guild.categories();
+    f(&mut categories)?;
+    anyhow::ensure!(
+        categories.iter().any(|category| !category.archived),
+        "At least one category must be active"
+    );
+    guild.categories.lock().unwrap().replace(categories.clone());
+    Ok(categories)
+}
+
+fn position(categories: &[CategoryInfo], name: &str) -> Result<usize, Error> {
+    categories
+        .iter()
+        .position(|category| category.name == name || category.id.0 == name)
+        .with_context(|| format!("Category not found: {}", name))
+}
+
+fn ensure_unique(categories: &[CategoryInfo], name: &str, id: &Category) -> Result<(), Error> {
+    anyhow::ensure!(
+        !categories
+            .iter()
+            .any(|category| category.id != *id && (category.name == name || category.id.0 == name)),
+        "Category already exists: {}",
+        name
+    );
+    Ok(())
+}
+
+async fn reply(
+    ctx: PoiseContext<'_>,
+    title: &str,
+    categories: &[CategoryInfo],
+) -> Result<(), Error> {
+    ctx.send(
+        poise::CreateReply::default().embed(
+            CreateEmbed::default()
+                .title(title)
+                .description(format_categories(categories))
+                .color(Color::DARK_BLUE),
+        ),
+    )
+    .await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD")]
+/// タスクの種類を追加します。
+pub async fn add_category(
+    ctx: PoiseContext<'_>,
+    #[description = "種類の名前"] name: String,
+    #[description = "名前の前に付ける絵文字"] emoji: Option<String>,
+    #[description = "通知の色 (例: #FF0000)"] color: Option<String>,
+    #[description = "期限接近通知の対象にする (既定: はい)"] warn: Option<bool>,
+    #[description = "ダイジェストの持ち物にまとめる (既定: いいえ)"] belongings: Option<bool>,
+) -> Result<(), Error> {
+    let (guild_id, guild) = guild_data(ctx)?;
+
+    let category = CategoryInfo {
+        id: Category::generate(),
+        name,
+        emoji,
+        color: color.as_deref().map(parse_color).transpose()?,
+        archived: false,
+        warn: warn.unwrap_or(true),
+        belongings: belongings.unwrap_or(false),
+    };
+    let categories = modify_categories(&guild, |categories| {
+        ensure_unique(categories, &category.name, &category.id)?;
+        categories.push(category);
+        Ok(())
+    })?;
+    data::save(ctx.data(), guild_id)?;
+
+    reply(ctx, "種類を追加しました", &categories).await
+}
+
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD")]
+/// タスクの種類の名前や表示、設定を変更します。
+pub async fn edit_category(
+    ctx: PoiseContext<'_>,
+    #[description = "変更する種類"]
+    #[autocomplete = "autocomplete_category"]
+    category: String,
+    #[description = "新しい名前"] name: Option<String>,
+    #[description = "名前の前に付ける絵文字 (「なし」で削除)"] emoji: Option<String>,
+    #[description = "通知の色 (例: #FF0000、「なし」で削除)"] color: Option<String>,
+    #[description = "期限接近通知の対象にする"] warn: Option<bool>,
+    #[description = "ダイジェストの持ち物にまとめる"] belongings: Option<bool>,
+) -> Result<(), Error> {
+    let (guild_id, guild) = guild_data(ctx)?;
+
+    let color = match color.as_deref() {
+        Some("なし") => Some(None),
+        Some(color) => Some(Some(parse_color(color)?)),
+        None => None,
+    };
+    let categories = modify_categories(&guild, |categories| {
+        let i = position(categories, &category)?;
+        if let Some(name) = name {
+            ensure_unique(categories, &name, &categories[i].id.clone())?;
+            categories[i].name = name;
+        }
+        if let Some(emoji) = emoji {
+            categories[i].emoji = (emoji != "なし").then_some(emoji);
+        }
+        if let Some(color) = color {
+            categories[i].color = color;
+        }
+        if let Some(warn) = warn {
+            categories[i].warn = warn;
+        }
+        if let Some(belongings) = belongings {
+            categories[i].belongings = belongings;
+        }
+        Ok(())
+    })?;
+    data::save(ctx.data(), guild_id)?;
+
+    reply(ctx, "種類を変更しました", &categories).await
+}
+
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD")]
+/// タスクの種類をアーカイブします。既存のタスクはそのまま残ります。
+pub async fn archive_category(
+    ctx: PoiseContext<'_>,
+    #[description = "アーカイブする種類"]
+    #[autocomplete = "autocomplete_category"]
+    category: String,
+    #[description = "アーカイブを解除する"] restore: Option<bool>,
+) -> Result<(), Error> {
+    let (guild_id, guild) = guild_data(ctx)?;
+
+    let categories = modify_categories(&guild, |categories| {
+        let i = position(categories, &category)?;
+        categories[i].archived = !restore.unwrap_or(false);
+        Ok(())
+    })?;
+    data::save(ctx.data(), guild_id)?;
+
+    reply(ctx, "種類を変更しました", &categories).await
+}
+
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD")]
+/// タスクの種類の並び順を変更します。
+pub async fn move_category(
+    ctx: PoiseContext<'_>,
+    #[description = "移動する種類"]
+    #[autocomplete = "autocomplete_category"]
+    category: String,
+    #[description = "移動先の順番 (1が先頭)"] position: u32,
+) -> Result<(), Error> {
+    let (guild_id, guild) = guild_data(ctx)?;
+
+    let categories = modify_categories(&guild, |categories| {
+        let i = self::position(categories, &category)?;
+        let moved = categories.remove(i);
+        categories.insert(
+            (position as usize).clamp(1, categories.len() + 1) - 1,
+            moved,
+        );
+        Ok(())
+    })?;
+    data::save(ctx.data(), guild_id)?;
+
+    reply(ctx, "種類の並び順を変更しました", &categories).await
+}
+
+#[poise::command(slash_command, guild_only)]
+/// タスクの種類の一覧を表示します。
+pub async fn list_categories(ctx: PoiseContext<'_>) -> Result<(), Error> {
+    let (_, guild) = guild_data(ctx)?;
+
+    reply(ctx, "タスクの種類", &guild.categories()).await
+}
@@ -0,0 +1,161 @@
+use anyhow::{Context as _, Error};
+use chrono::{NaiveTime, Utc};
+use poise::serenity_prelude::*;
+
+use crate::{
+    PoiseContext,
+    data::{self, Digest, DigestWindow, GuildData},
+    utilities::{guild_data, parse_weekdays},
+};
+
+fn format_digests(digests: &[Digest]) -> String {
+    if digests.is_empty() {
+        return "なし".into();
+    }
+    digests
+        .iter()
+        .map(|digest| format!("- **{}**: {}", digest.title, digest.describe()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// ダイジェストの一覧を変更します。以前の設定から作られたダイジェストも引き継ぎます。
+fn modify_digests(
+    guild: &GuildData,
+    f: impl FnOnce(&mut Vec<Digest>) -> Result<(), Error>,
+) -> Result<Vec<Digest>, Error> {
+    let mut digests = guild.digests();
+    f(&mut digests)?;
+    guild.digests.lock().unwrap().replace(digests.clone());
+    Ok(digests)
+}
+
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD")]
+/// タスクをまとめて通知するダイジェストを追加・上書きします。
+pub async fn add_digest(
+    ctx: PoiseContext<'_>,
+    #[description = "通知のタイトル"] title: String,
+    #[description = "送る時刻 (例: 07:30)"] time: String,
+    #[description = "送る曜日 (例: 月水金、平日、毎日)"] weekdays: Option<String>,
+    #[description = "含めるタスク (例: 1=明日、3=明日から3日間、月=次の月曜日まで)"] window: Option<
+        String,
+    >,
+    #[description = "送るチャンネル"] channel: Option<Channel>,
+    #[description = "タスクがあるときにメンションするロール"] role: Option<Role>,
+) -> Result<(), Error> {
+    let (guild_id, guild) = guild_data(ctx)?;
+
+    let time = NaiveTime::parse_from_str(&time, "%H:%M")
+        .with_context(|| format!("Invalid time: {}", time))?;
+    let weekdays = parse_weekdays(weekdays.as_deref().unwrap_or_default())?;
+    let window = match window.as_deref().map(str::trim) {
+        None => DigestWindow::Days(1),
+        Some(window) => match window.parse::<u32>() {
+            Ok(days @ 1..=31) => DigestWindow::Days(days),
+            Ok(_) => anyhow::bail!("Days must be between 1 and 31"),
+            Err(_) => match parse_weekdays(window)?.as_slice() {
+                [weekday] => DigestWindow::UntilNext(*weekday),
+                _ => anyhow::bail!("Invalid window: {}", window),
+            },
+        },
+    };
+    let digest = Digest {
+        title,
+        time,
+        weekdays,
+        window,
+        channel: channel.map(|c| c.id()).unwrap_or(ctx.channel_id()),
+        role: role.map(|role| role.id),
+    };
+
+    // 設定した時点より前の分は送らない
+    guild
+        .sent_digests
+        .lock()
+        .unwrap()
+        .insert(digest.title.clone(), Utc::now());
+    let digests = modify_digests(&guild, |digests| {
+        digests.retain(|d| d.title != digest.title);
+        digests.push(digest);
+        Ok(())
+    })?;
+    data::save(ctx.data(), guild_id)?;
+
+    ctx.send(
+        poise::CreateReply::default().embed(
+            CreateEmbed::default()
+                .title("ダイジェストを追加しました")
+                .description(format_digests(&digests))
+                .color(Color::DARK_BLUE),
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+async fn autocomplete_title<'a>(ctx: PoiseContext<'a>, partial: &'a str) -> Vec<String> {
+    let Ok((_, guild)) = guild_data(ctx) else {
+        return vec![];
+    };
+    guild
+        .digests()
+        .into_iter()
+        .map(|digest| digest.title)
+        .filter(|title| title.contains(partial))
+        .take(25)
+        .collect()
+}
+
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD")]
+/// ダイジェストを削除します。
+pub async fn remove_digest(
+    ctx: PoiseContext<'_>,
+    #[description = "削除するダイジェストのタイトル"]
+    #[autocomplete = "autocomplete_title"]
+    title: String,
+) -> Result<(), Error> {
+    let (guild_id, guild) = guild_data(ctx)?;
+
+    let digests = modify_digests(&guild, |digests| {
+        let len = digests.len();
+        digests.retain(|digest| digest.title != title);
+        anyhow::ensure!(digests.len() < len, "Digest not found");
+        Ok(())
+    })?;
+    data::save(ctx.data(), guild_id)?;
+
+    ctx.send(
+        poise::CreateReply::default().embed(
+            CreateEmbed::default()
+                .title("ダイジェストを削除しました")
+                .description(format_digests(&digests))
+                .color(Color::DARK_BLUE),
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only)]
+/// ダイジェストの一覧を表示します。
+pub async fn list_digests(ctx: PoiseContext<'_>) -> Result<(), Error> {
+    let (_, guild) = guild_data(ctx)?;
+
+    ctx.send(
+        poise::CreateReply::default().embed(
+            CreateEmbed::default()
+                .title("ダイジェスト")
+                .description(format!(
+                    "{}\n\nタイムゾーン: {}",
+                    format_digests(&guild.digests()),
+                    guild.timezone()
+                ))
+                .color(Color::DARK_BLUE),
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
@@ -2,22 +2,20 @@ use anyhow::Error;
 
 use poise::serenity_prelude::*;
 
-use crate::{data, PoiseContext};
+use crate::{data, utilities::guild_data, PoiseContext};
 
-#[poise::command(slash_command, required_permissions = "MANAGE_GUILD")]
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD")]
 /// 管理者向けログを送るチャンネルを設定します。
 pub async fn set_log_channel(
     ctx: PoiseContext<'_>,
     #[description = "管理者向けログを送るチャンネル"] channel: Option<Channel>,
 ) -> Result<(), Error> {
+    let (guild_id, guild) = guild_data(ctx)?;
+
     let channel_id = channel.map(|c| c.id()).unwrap_or(ctx.channel_id());
 
-    ctx.data()
-        .log_channel
-        .lock()
-        .unwrap()
-        .replace(channel_id);
-    data::save(ctx.data())?;
+    guild.log_channel.lock().unwrap().replace(channel_id);
+    data::save(ctx.data(), guild_id)?;
 
     ctx.send(
         poise::CreateReply::default().embed(
@@ -1,7 +1,18 @@
 pub mod warn_config;
+pub mod belongings;
+pub mod calendar;
+pub mod category_config;
+pub mod digest_config;
 pub mod log_config;
 pub mod modify_subjects;
 pub mod modify_suggest_times;
 pub mod modify_tasks;
 pub mod panel;
 pub mod ping_config;
+pub mod recurring_task;
+pub mod restore_backup;
+pub mod spreadsheet;
+pub mod timetable;
+pub mod timezone_config;
+pub mod trash;
+pub mod webhook_config;
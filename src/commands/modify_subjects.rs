@@ -4,29 +4,38 @@ use anyhow::{Context as _, Error};
 use futures::StreamExt;
 use poise::serenity_prelude::*;
 
-use crate::{data, PoiseContext};
+use crate::{
+    data,
+    interactions::select_reassign,
+    periodic::ping,
+    subjects,
+    utilities::{autocomplete_subject, guild_data, ResponsiveInteraction},
+    PoiseContext,
+};
 
-#[poise::command(slash_command)]
+#[poise::command(slash_command, guild_only)]
 /// 教科を追加します。
 pub async fn add_subjects(
     ctx: PoiseContext<'_>,
     #[description = "追加したい教科 / カンマ区切りで複数追加できます"] subjects: String,
 ) -> Result<(), Error> {
+    let (guild_id, guild) = guild_data(ctx)?;
+
     let subjects = subjects
         .split(',')
         .map(|s| s.trim().to_string())
         .collect::<Vec<_>>();
 
-    ctx.data()
+    guild
         .subjects
         .lock()
         .unwrap()
         .extend(subjects.clone().into_iter());
-    data::save(ctx.data())?;
+    data::save(ctx.data(), guild_id)?;
 
     let diff = format!(
         "```diff\n{}\n```",
-        ctx.data()
+        guild
             .subjects
             .lock()
             .unwrap()
@@ -49,13 +58,100 @@ pub async fn add_subjects(
     Ok(())
 }
 
-#[poise::command(slash_command)]
-/// 教科を削除します。
+#[poise::command(slash_command, guild_only)]
+/// 教科の名前を変更します。タスクや時間割なども変更されます。
+pub async fn rename_subject(
+    ctx: PoiseContext<'_>,
+    #[description = "名前を変更する教科"]
+    #[autocomplete = "autocomplete_subject"]
+    subject: String,
+    #[description = "新しい名前"] new_name: String,
+) -> Result<(), Error> {
+    let (guild_id, guild) = guild_data(ctx)?;
+
+    let new_name = new_name.trim().to_string();
+    anyhow::ensure!(!new_name.is_empty(), "Subject name must not be empty");
+    {
+        let subjects = guild.subjects.lock().unwrap();
+        anyhow::ensure!(
+            subjects.contains(&subject),
+            "Subject not found: {}",
+            subject
+        );
+        anyhow::ensure!(
+            !subjects.contains(&new_name),
+            "Subject already exists: {} (use /merge_subjects)",
+            new_name
+        );
+    }
+    let count = subjects::replace(ctx.data(), guild_id, &subject, Some(&new_name))?;
+
+    ctx.send(
+        poise::CreateReply::default().embed(
+            CreateEmbed::default()
+                .title("名前を変更しました")
+                .description(format!(
+                    "{} → {}\n{}件のタスクを変更しました",
+                    subject, new_name, count
+                ))
+                .color(Color::DARK_GREEN),
+        ),
+    )
+    .await?;
+
+    ping::update(&ctx).await?;
+
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only)]
+/// 教科を別の教科にまとめます。まとめた教科は削除されます。
+pub async fn merge_subjects(
+    ctx: PoiseContext<'_>,
+    #[description = "まとめて削除する教科"]
+    #[autocomplete = "autocomplete_subject"]
+    from: String,
+    #[description = "まとめる先の教科"]
+    #[autocomplete = "autocomplete_subject"]
+    into: String,
+) -> Result<(), Error> {
+    let (guild_id, guild) = guild_data(ctx)?;
+
+    anyhow::ensure!(from != into, "Cannot merge a subject into itself");
+    {
+        let subjects = guild.subjects.lock().unwrap();
+        for subject in [&from, &into] {
+            anyhow::ensure!(subjects.contains(subject), "Subject not found: {}", subject);
+        }
+    }
+    let count = subjects::replace(ctx.data(), guild_id, &from, Some(&into))?;
+
+    ctx.send(
+        poise::CreateReply::default().embed(
+            CreateEmbed::default()
+                .title("教科をまとめました")
+                .description(format!(
+                    "{} → {}\n{}件のタスクを変更しました",
+                    from, into, count
+                ))
+                .color(Color::DARK_GREEN),
+        ),
+    )
+    .await?;
+
+    ping::update(&ctx).await?;
+
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only)]
+/// 教科を削除します。タスクは別の教科に付け替えるか教科なしにします。
 pub async fn remove_subject(ctx: PoiseContext<'_>) -> Result<(), Error> {
     const SUBJECT: &str = "subject";
     const SUBMIT: &str = "submit";
 
-    let subjects = ctx.data().subjects.lock().unwrap().clone();
+    let (guild_id, guild) = guild_data(ctx)?;
+    let subjects = guild.subjects.lock().unwrap().clone();
 
     let components = |selected_subject: Option<String>| {
         let subject_options = CreateSelectMenuKind::String {
@@ -125,9 +221,27 @@ pub async fn remove_subject(ctx: PoiseContext<'_>) -> Result<(), Error> {
     }
 
     let subject = select.context("Subject not selected")?;
+    let mut last_interaction =
+        ResponsiveInteraction::Component(last_interaction.context("No interaction")?);
+
+    let count = subjects::usage(ctx.data(), guild_id, &subject)?;
+    let mut reassign = None;
+    if count > 0 {
+        let (interaction, to) = select_reassign(
+            ctx,
+            last_interaction,
+            &subject,
+            count,
+            &subjects.iter().cloned().collect::<Vec<_>>(),
+        )
+        .await?;
+        last_interaction = interaction;
+        reassign = to;
+    }
+
     let diff = format!(
         "```diff\n{}\n```",
-        ctx.data()
+        guild
             .subjects
             .lock()
             .unwrap()
@@ -137,12 +251,12 @@ pub async fn remove_subject(ctx: PoiseContext<'_>) -> Result<(), Error> {
             .join("\n")
     );
 
-    ctx.data()
-        .subjects
-        .lock()
-        .unwrap()
-        .retain(|s| s != &subject);
-    data::save(ctx.data())?;
+    subjects::replace(ctx.data(), guild_id, &subject, reassign.as_deref())?;
+    let diff = match (count, &reassign) {
+        (0, _) => diff,
+        (_, Some(to)) => format!("{}\n{}件のタスクを{}に付け替えました", diff, count, to),
+        (_, None) => format!("{}\n{}件のタスクを教科なしにしました", diff, count),
+    };
 
     let response = CreateInteractionResponse::UpdateMessage(
         CreateInteractionResponseMessage::default()
@@ -155,10 +269,11 @@ pub async fn remove_subject(ctx: PoiseContext<'_>) -> Result<(), Error> {
             .components(vec![]),
     );
 
-    last_interaction
-        .context("No interaction")?
-        .create_response(&ctx, response)
-        .await?;
+    last_interaction.create_response(ctx, response).await?;
+
+    if count > 0 {
+        ping::update(&ctx).await?;
+    }
 
     Ok(())
 }
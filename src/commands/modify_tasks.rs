@@ -2,16 +2,19 @@ use anyhow::{Context, Error};
 use poise::serenity_prelude::*;
 
 use crate::{
-    data,
-    interactions::{create_task, select_announce, select_task},
+    interactions::{create_task, select_announce, select_scope, select_task},
     periodic::ping,
+    recurrence,
+    utilities::guild_data,
     PartialTask, PoiseContext,
 };
 
-#[poise::command(slash_command)]
+#[poise::command(slash_command, guild_only)]
 /// タスクを追加します。
 pub async fn add_task(ctx: PoiseContext<'_>) -> Result<(), Error> {
-    let ping_role = (*ctx.data().ping_role.lock().unwrap()).context("Ping role not set")?;
+    let (guild_id, guild) = guild_data(ctx)?;
+
+    let ping_role = (*guild.ping_role.lock().unwrap()).context("Ping role not set")?;
 
     let (mut last_interaction, task) = create_task(
         ctx,
@@ -25,12 +28,11 @@ pub async fn add_task(ctx: PoiseContext<'_>) -> Result<(), Error> {
     )
     .await?;
 
-    ctx.data().tasks.lock().unwrap().insert(task.clone());
-    data::save(ctx.data())?;
+    ctx.data().add_task(guild_id, ctx.author().id, &task)?;
 
     let embed = CreateEmbed::default()
         .title("タスクを追加しました")
-        .fields(vec![task.to_field()])
+        .fields(vec![task.to_field(&guild)])
         .color(Color::DARK_GREEN);
 
     if let Some(message) = ping::update(&ctx).await?.first() {
@@ -63,10 +65,12 @@ pub async fn add_task(ctx: PoiseContext<'_>) -> Result<(), Error> {
     Ok(())
 }
 
-#[poise::command(slash_command)]
+#[poise::command(slash_command, guild_only)]
 /// タスクを削除します。
 pub async fn remove_task(ctx: PoiseContext<'_>) -> Result<(), Error> {
-    let ping_role = (*ctx.data().ping_role.lock().unwrap()).context("Ping role not set")?;
+    let (guild_id, guild) = guild_data(ctx)?;
+
+    let ping_role = (*guild.ping_role.lock().unwrap()).context("Ping role not set")?;
 
     let (mut last_interaction, task) = select_task(
         ctx,
@@ -79,15 +83,11 @@ pub async fn remove_task(ctx: PoiseContext<'_>) -> Result<(), Error> {
     )
     .await?;
 
-    {
-        let mut tasks = ctx.data().tasks.lock().unwrap();
-        tasks.remove(&task);
-    }
-    data::save(ctx.data())?;
+    ctx.data().remove_task(guild_id, ctx.author().id, task.id)?;
 
     let embed = CreateEmbed::default()
         .title("タスクを削除しました")
-        .fields(vec![task.to_field()])
+        .fields(vec![task.to_field(&guild)])
         .color(Color::DARK_RED);
 
     if let Some(message) = ping::update(&ctx).await?.first() {
@@ -122,10 +122,12 @@ pub async fn remove_task(ctx: PoiseContext<'_>) -> Result<(), Error> {
     Ok(())
 }
 
-#[poise::command(slash_command)]
+#[poise::command(slash_command, guild_only)]
 /// タスクを編集します。
 pub async fn edit_task(ctx: PoiseContext<'_>) -> Result<(), Error> {
-    let ping_role = (*ctx.data().ping_role.lock().unwrap()).context("Ping role not set")?;
+    let (guild_id, guild) = guild_data(ctx)?;
+
+    let ping_role = (*guild.ping_role.lock().unwrap()).context("Ping role not set")?;
 
     let (last_interaction, task) = select_task(
         ctx,
@@ -146,25 +148,32 @@ pub async fn edit_task(ctx: PoiseContext<'_>) -> Result<(), Error> {
                 .title("タスクを編集します".to_string())
                 .color(Color::DARK_BLUE),
         ),
-        task.clone().into_partial(),
+        task.clone().into_partial(guild.timezone()),
     )
     .await?;
 
-    {
-        let mut tasks = ctx.data().tasks.lock().unwrap();
-        tasks.remove(&task);
-        tasks.insert(modified_task.clone());
+    let mut following = false;
+    if recurrence::series_of(ctx.data(), guild_id, task.id).is_some() {
+        (last_interaction, following) = select_scope(ctx, Some(last_interaction)).await?;
+    }
+    if following {
+        recurrence::edit_following(ctx.data(), guild_id, ctx.author().id, &modified_task)?;
+    } else {
+        ctx.data()
+            .edit_task(guild_id, ctx.author().id, &modified_task)?;
     }
-    data::save(ctx.data())?;
 
-    let embed = CreateEmbed::default()
+    let mut embed = CreateEmbed::default()
         .title("タスクを編集しました")
         .fields(vec![
-            task.to_field(),
+            task.to_field(&guild),
             ("↓".into(), "".into(), false),
-            modified_task.to_field(),
+            modified_task.to_field(&guild),
         ])
         .color(Color::DARK_GREEN);
+    if following {
+        embed = embed.description("以降の繰り返しにも反映しました");
+    }
 
     if let Some(message) = ping::update(&ctx).await?.first() {
         let announce;
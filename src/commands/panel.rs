@@ -1,12 +1,16 @@
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 
 use anyhow::{Context as _, Error};
-use chrono::Local;
+use chrono::Utc;
 use itertools::Itertools;
 use poise::serenity_prelude::*;
 use {Mentionable, futures::StreamExt};
 
-use crate::{PoiseContext, data};
+use crate::{
+    PoiseContext,
+    data::{self, Data},
+    utilities::guild_data,
+};
 
 const TASKS: &str = "tasks";
 const ARCHIVED_TASKS: &str = "archived_tasks";
@@ -18,6 +22,8 @@ pub async fn deploy_panel(
     ctx: PoiseContext<'_>,
     #[description = "パネルをデプロイするチャンネル"] channel: Option<Channel>,
 ) -> Result<(), Error> {
+    let (guild_id, guild) = guild_data(ctx)?;
+
     let message = channel
         .map(|c| c.id())
         .unwrap_or(ctx.channel_id())
@@ -43,23 +49,10 @@ pub async fn deploy_panel(
 
     let id_pair = (message.id, message.channel_id);
 
-    ctx.data().panel_message.lock().unwrap().replace(id_pair);
-    data::save(ctx.data())?;
-
-    ctx.data()
-        .panel_listener
-        .lock()
-        .unwrap()
-        .as_ref()
-        .inspect(|h| h.abort());
-    ctx.data()
-        .panel_listener
-        .lock()
-        .unwrap()
-        .replace(tokio::spawn(listen_panel_interactions(
-            ctx.serenity_context().clone(),
-            id_pair,
-        )));
+    guild.panel_message.lock().unwrap().replace(id_pair);
+    data::save(ctx.data(), guild_id)?;
+
+    restart_listener(ctx.serenity_context(), ctx.data(), guild_id);
 
     ctx.send(
         poise::CreateReply::default()
@@ -75,8 +68,25 @@ pub async fn deploy_panel(
     Ok(())
 }
 
+/// パネルのリスナーを停止し、パネルが設定されていれば起動し直します。
+pub fn restart_listener(ctx: &Context, data: &Arc<Data>, guild_id: GuildId) {
+    let guild = data.guild(guild_id);
+    let mut listener = guild.panel_listener.lock().unwrap();
+    listener.as_ref().inspect(|h| h.abort());
+    *listener = (*guild.panel_message.lock().unwrap()).map(|id_pair| {
+        tokio::spawn(listen_panel_interactions(
+            ctx.clone(),
+            data.clone(),
+            guild_id,
+            id_pair,
+        ))
+    });
+}
+
 pub async fn listen_panel_interactions(
     ctx: Context,
+    data: Arc<Data>,
+    guild_id: GuildId,
     id_pair: (MessageId, ChannelId),
 ) -> Result<(), Error> {
     let (message_id, channel_id) = id_pair;
@@ -86,10 +96,20 @@ pub async fn listen_panel_interactions(
     while let Some(interaction) = interaction_stream.next().await {
         match interaction.data.custom_id.as_str() {
             TASKS => {
-                tokio::spawn(show_tasks(interaction.clone(), ctx.clone()));
+                tokio::spawn(show_tasks(
+                    interaction.clone(),
+                    ctx.clone(),
+                    data.clone(),
+                    guild_id,
+                ));
             }
             ARCHIVED_TASKS => {
-                tokio::spawn(show_archived_tasks(interaction.clone(), ctx.clone()));
+                tokio::spawn(show_archived_tasks(
+                    interaction.clone(),
+                    ctx.clone(),
+                    data.clone(),
+                    guild_id,
+                ));
             }
             _ => unreachable!(),
         }
@@ -98,8 +118,14 @@ pub async fn listen_panel_interactions(
     Ok(())
 }
 
-async fn log(ctx: &Context, user: &User, message: impl Into<String>) -> Result<(), Error> {
-    let log_channel = *data::load()?.log_channel.lock().unwrap();
+async fn log(
+    ctx: &Context,
+    data: &Data,
+    guild_id: GuildId,
+    user: &User,
+    message: impl Into<String>,
+) -> Result<(), Error> {
+    let log_channel = *data.guild(guild_id).log_channel.lock().unwrap();
 
     log_channel
         .context("log channel not set")?
@@ -113,7 +139,7 @@ async fn log(ctx: &Context, user: &User, message: impl Into<String>) -> Result<(
                             .icon_url(user.avatar_url().unwrap_or_default()),
                     )
                     .title("パネル操作")
-                    .timestamp(Local::now())
+                    .timestamp(Utc::now())
                     .description(message)
                     .color(Color::DARK_BLUE),
             ),
@@ -123,21 +149,33 @@ async fn log(ctx: &Context, user: &User, message: impl Into<String>) -> Result<(
     Ok(())
 }
 
-async fn show_tasks(interaction: ComponentInteraction, ctx: Context) -> Result<(), Error> {
+async fn show_tasks(
+    interaction: ComponentInteraction,
+    ctx: Context,
+    data: Arc<Data>,
+    guild_id: GuildId,
+) -> Result<(), Error> {
     const PREV: &str = "prev";
     const NEXT: &str = "next";
 
-    let tasks = data::load()?.tasks.lock().unwrap().clone();
+    let tasks = data.storage.tasks(guild_id)?;
+    let guild = data.guild(guild_id);
+    let tz = guild.timezone();
+    let today = Utc::now().with_timezone(&tz).date_naive();
 
     let mut page = 0;
     let message = |page: usize| {
-        let fields = tasks
+        let order = guild.task_order();
+        let tasks = tasks
             .iter()
-            .filter(|e| Local::now().date_naive() <= e.datetime.date_naive())
-            .sorted_by_key(|e| e.datetime)
-            .map(|task| task.to_field())
+            .filter(|e| today <= e.datetime.with_timezone(&tz).date_naive())
+            .sorted_by_key(|e| order(e))
             .skip(TASKS_PER_PAGE * page)
             .collect::<Vec<_>>();
+        let fields = tasks
+            .iter()
+            .map(|task| task.to_field(&guild))
+            .collect::<Vec<_>>();
 
         CreateInteractionResponseMessage::new()
             .embed(
@@ -149,7 +187,7 @@ async fn show_tasks(interaction: ComponentInteraction, ctx: Context) -> Result<(
                         ""
                     })
                     .fields(fields.clone().into_iter().take(TASKS_PER_PAGE))
-                    .color(Color::DARK_BLUE),
+                    .color(guild.list_color(tasks.iter().copied(), Color::DARK_BLUE)),
             )
             .components(vec![CreateActionRow::Buttons(vec![
                 CreateButton::new(PREV)
@@ -170,6 +208,8 @@ async fn show_tasks(interaction: ComponentInteraction, ctx: Context) -> Result<(
 
     log(
         &ctx,
+        &data,
+        guild_id,
         &interaction.user,
         format!(
             "{}さんがタスク一覧を確認しました",
@@ -212,22 +252,32 @@ async fn show_tasks(interaction: ComponentInteraction, ctx: Context) -> Result<(
     Ok(())
 }
 
-async fn show_archived_tasks(interaction: ComponentInteraction, ctx: Context) -> Result<(), Error> {
+async fn show_archived_tasks(
+    interaction: ComponentInteraction,
+    ctx: Context,
+    data: Arc<Data>,
+    guild_id: GuildId,
+) -> Result<(), Error> {
     const PREV: &str = "prev";
     const NEXT: &str = "next";
 
-    let tasks = data::load()?.tasks.lock().unwrap().clone();
+    let tasks = data.storage.tasks(guild_id)?;
+    let guild = data.guild(guild_id);
 
     let mut page = 0;
     let message = |page: usize| {
-        let fields = tasks
+        let order = guild.task_order();
+        let tasks = tasks
             .iter()
-            .filter(|e| Local::now() > e.datetime)
-            .sorted_by_key(|e| e.datetime)
+            .filter(|e| Utc::now() > e.datetime)
+            .sorted_by_key(|e| order(e))
             .rev()
-            .map(|task| task.to_field())
             .skip(TASKS_PER_PAGE * page)
             .collect::<Vec<_>>();
+        let fields = tasks
+            .iter()
+            .map(|task| task.to_field(&guild))
+            .collect::<Vec<_>>();
 
         CreateInteractionResponseMessage::new()
             .embed(
@@ -239,7 +289,7 @@ async fn show_archived_tasks(interaction: ComponentInteraction, ctx: Context) ->
                         ""
                     })
                     .fields(fields.clone().into_iter().take(TASKS_PER_PAGE))
-                    .color(Color::DARK_BLUE),
+                    .color(guild.list_color(tasks.iter().copied(), Color::DARK_BLUE)),
             )
             .components(vec![CreateActionRow::Buttons(vec![
                 CreateButton::new(PREV)
@@ -260,6 +310,8 @@ async fn show_archived_tasks(interaction: ComponentInteraction, ctx: Context) ->
 
     log(
         &ctx,
+        &data,
+        guild_id,
         &interaction.user,
         format!(
             "{}さんが過去のタスク一覧を確認しました",
@@ -1,8 +1,12 @@
 use anyhow::{Context as _, Error};
-use chrono::{Local, TimeZone};
+use chrono::{NaiveTime, Utc};
 use poise::serenity_prelude::*;
 
-use crate::{PoiseContext, data, interactions::select_date};
+use crate::{
+    PoiseContext, data,
+    interactions::select_date,
+    utilities::{guild_data, to_utc},
+};
 
 #[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD")]
 /// タスク通知を送るチャンネルを設定します。
@@ -10,10 +14,12 @@ pub async fn set_ping_channel(
     ctx: PoiseContext<'_>,
     #[description = "タスク通知を送るチャンネル"] channel: Option<Channel>,
 ) -> Result<(), Error> {
+    let (guild_id, guild) = guild_data(ctx)?;
+
     let channel_id = channel.map(|c| c.id()).unwrap_or(ctx.channel_id());
 
-    ctx.data().ping_channel.lock().unwrap().replace(channel_id);
-    data::save(ctx.data())?;
+    guild.ping_channel.lock().unwrap().replace(channel_id);
+    data::save(ctx.data(), guild_id)?;
 
     ctx.send(
         poise::CreateReply::default().embed(
@@ -34,8 +40,10 @@ pub async fn set_ping_role(
     ctx: PoiseContext<'_>,
     #[description = "タスク通知を送るロール"] role: Role,
 ) -> Result<(), Error> {
-    ctx.data().ping_role.lock().unwrap().replace(role.id);
-    data::save(ctx.data())?;
+    let (guild_id, guild) = guild_data(ctx)?;
+
+    guild.ping_role.lock().unwrap().replace(role.id);
+    data::save(ctx.data(), guild_id)?;
 
     ctx.send(
         poise::CreateReply::default()
@@ -55,6 +63,8 @@ pub async fn set_ping_role(
 #[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD")]
 /// タスク通知をある日付まで停止します。
 pub async fn stop_ping(ctx: PoiseContext<'_>) -> Result<(), Error> {
+    let (guild_id, guild) = guild_data(ctx)?;
+
     let (last_interaction, date) = select_date(
         ctx,
         None,
@@ -68,13 +78,11 @@ pub async fn stop_ping(ctx: PoiseContext<'_>) -> Result<(), Error> {
     .await
     .context("No date selected")?;
 
-    let date = Local
-        .from_local_datetime(&date.and_hms_opt(0, 0, 0).unwrap())
-        .unwrap();
+    let date = to_utc(guild.timezone(), date.and_time(NaiveTime::MIN));
     let timestamp = date.timestamp();
 
-    *ctx.data().stop_ping_until.lock().unwrap() = date;
-    data::save(ctx.data())?;
+    *guild.stop_ping_until.lock().unwrap() = date;
+    data::save(ctx.data(), guild_id)?;
 
     last_interaction
         .create_response(
@@ -99,8 +107,10 @@ pub async fn stop_ping(ctx: PoiseContext<'_>) -> Result<(), Error> {
 #[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD")]
 /// 通知の停止を解除します。
 pub async fn resume_ping(ctx: PoiseContext<'_>) -> Result<(), Error> {
-    *ctx.data().stop_ping_until.lock().unwrap() = Local::now();
-    data::save(ctx.data())?;
+    let (guild_id, guild) = guild_data(ctx)?;
+
+    *guild.stop_ping_until.lock().unwrap() = Utc::now();
+    data::save(ctx.data(), guild_id)?;
 
     ctx.send(
         poise::CreateReply::default().embed(
@@ -0,0 +1,184 @@
+use anyhow::Error;
+use poise::serenity_prelude::*;
+use uuid::Uuid;
+
+use crate::{
+    PartialTask, PoiseContext,
+    data::{self, GuildData, Recurrence, RecurringTask},
+    interactions::create_task,
+    periodic::ping,
+    recurrence,
+    utilities::{guild_data, parse_naive_date, parse_weekdays},
+};
+
+fn format_recurring_tasks(recurring_tasks: &[RecurringTask], guild: &GuildData) -> String {
+    if recurring_tasks.is_empty() {
+        return "(なし)".into();
+    }
+    recurring_tasks
+        .iter()
+        .map(|series| format!("- {}", series.describe(guild)))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[poise::command(slash_command, guild_only)]
+/// 繰り返しのタスクを追加します。曜日・間隔・日のどれかを指定します。
+pub async fn add_recurring_task(
+    ctx: PoiseContext<'_>,
+    #[description = "毎週繰り返す曜日 (例: 木、月水金、平日、毎日)"] weekdays: Option<String>,
+    #[description = "繰り返す間隔の日数"] interval_days: Option<u32>,
+    #[description = "毎月繰り返す日"] day_of_month: Option<u32>,
+    #[description = "終了日 (例: 2025-03-31)"] until: Option<String>,
+    #[description = "除外する日 (例: 2025-02-13,2025-02-20)"] exceptions: Option<String>,
+) -> Result<(), Error> {
+    let (guild_id, guild) = guild_data(ctx)?;
+    let tz = guild.timezone();
+
+    let recurrence = match (weekdays, interval_days, day_of_month) {
+        (Some(weekdays), None, None) => Recurrence::Weekly(parse_weekdays(&weekdays)?),
+        (None, Some(days), None) => {
+            anyhow::ensure!(days >= 1, "Invalid interval: {}", days);
+            Recurrence::Days(days)
+        }
+        (None, None, Some(day)) => {
+            anyhow::ensure!((1..=31).contains(&day), "Invalid day of month: {}", day);
+            Recurrence::Monthly(day)
+        }
+        _ => anyhow::bail!("Specify exactly one of weekdays, interval_days and day_of_month"),
+    };
+    let until = until.as_deref().map(parse_naive_date).transpose()?;
+    let exceptions = exceptions
+        .iter()
+        .flat_map(|exceptions| exceptions.split([',', '、']))
+        .map(|date| parse_naive_date(date.trim()))
+        .collect::<Result<_, _>>()?;
+
+    let (last_interaction, task) = create_task(
+        ctx,
+        None,
+        Some(
+            CreateEmbed::default()
+                .title("繰り返しのタスクを追加します")
+                .description(format!(
+                    "{}繰り返します。日付には初回の日を選択してください",
+                    recurrence
+                ))
+                .color(Color::DARK_BLUE),
+        ),
+        PartialTask::default(),
+    )
+    .await?;
+
+    let datetime = task.datetime.with_timezone(&tz);
+    let series = RecurringTask {
+        id: Uuid::new_v4(),
+        category: task.category,
+        subject: task.subject,
+        details: task.details,
+        time: datetime.time(),
+        start: datetime.date_naive(),
+        recurrence,
+        until,
+        exceptions,
+        occurrences: Default::default(),
+    };
+    let description = series.describe(&guild);
+    guild.recurring_tasks.lock().unwrap().push(series);
+    data::save(ctx.data(), guild_id)?;
+    recurrence::materialize(ctx.data(), guild_id)?;
+
+    let response = CreateInteractionResponse::UpdateMessage(
+        CreateInteractionResponseMessage::default()
+            .embed(
+                CreateEmbed::default()
+                    .title("繰り返しのタスクを追加しました")
+                    .description(description)
+                    .color(Color::DARK_GREEN),
+            )
+            .components(vec![]),
+    );
+    last_interaction.create_response(ctx, response).await?;
+
+    ping::update(&ctx).await?;
+
+    Ok(())
+}
+
+async fn autocomplete_recurring_task<'a>(
+    ctx: PoiseContext<'a>,
+    partial: &'a str,
+) -> Vec<AutocompleteChoice> {
+    let Ok((_, guild)) = guild_data(ctx) else {
+        return vec![];
+    };
+    guild
+        .recurring_tasks
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|series| (series.describe(&guild), series.id))
+        .filter(|(description, _)| description.contains(partial))
+        .take(25)
+        .map(|(description, id)| {
+            // 選択肢の名前は100文字まで
+            AutocompleteChoice::new(
+                description.chars().take(100).collect::<String>(),
+                id.to_string(),
+            )
+        })
+        .collect()
+}
+
+#[poise::command(slash_command, guild_only)]
+/// 繰り返しのタスクを削除します。今日以降の回はゴミ箱に入ります。
+pub async fn remove_recurring_task(
+    ctx: PoiseContext<'_>,
+    #[description = "削除する繰り返しのタスク"]
+    #[autocomplete = "autocomplete_recurring_task"]
+    recurring_task: String,
+) -> Result<(), Error> {
+    let (guild_id, guild) = guild_data(ctx)?;
+
+    let series = recurrence::remove(
+        ctx.data(),
+        guild_id,
+        ctx.author().id,
+        recurring_task.parse()?,
+    )?;
+    let remaining = format_recurring_tasks(&guild.recurring_tasks.lock().unwrap(), &guild);
+
+    ctx.send(
+        poise::CreateReply::default().embed(
+            CreateEmbed::default()
+                .title("繰り返しのタスクを削除しました")
+                .description(series.describe(&guild))
+                .field("繰り返しのタスク", remaining, false)
+                .color(Color::DARK_BLUE),
+        ),
+    )
+    .await?;
+
+    ping::update(&ctx).await?;
+
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only)]
+/// 繰り返しのタスクの一覧を表示します。
+pub async fn list_recurring_tasks(ctx: PoiseContext<'_>) -> Result<(), Error> {
+    let (_, guild) = guild_data(ctx)?;
+
+    let description = format_recurring_tasks(&guild.recurring_tasks.lock().unwrap(), &guild);
+    ctx.send(
+        poise::CreateReply::default().embed(
+            CreateEmbed::default()
+                .title("繰り返しのタスク")
+                .description(description)
+                .color(Color::DARK_BLUE),
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
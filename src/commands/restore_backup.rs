@@ -0,0 +1,178 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Context as _, Error};
+use itertools::Itertools;
+use poise::serenity_prelude::*;
+
+use crate::{
+    PoiseContext, Task,
+    commands::panel,
+    data::GuildData,
+    interactions::confirm,
+    periodic::ping,
+    storage,
+    utilities::{format_datetime, guild_data},
+};
+
+/// 差分の一覧に表示するタスクの最大数です。
+const MAX_LISTED_TASKS: usize = 10;
+
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD")]
+/// バックアップからデータを復元します。
+pub async fn restore_backup(
+    ctx: PoiseContext<'_>,
+    #[description = "バックアップのJSONファイル"] file: Option<Attachment>,
+    #[description = "バックアップが投稿されたメッセージのリンク"] message_link: Option<String>,
+) -> Result<(), Error> {
+    let (guild_id, guild) = guild_data(ctx)?;
+
+    ctx.defer().await?;
+
+    let attachment = match (file, message_link) {
+        (Some(file), None) => file,
+        (None, Some(link)) => {
+            let (link_guild, channel_id, message_id) =
+                parse_message_url(&link).context("Invalid message link")?;
+            anyhow::ensure!(link_guild == guild_id, "Message is not in this guild");
+            let message = channel_id.message(ctx, message_id).await?;
+            anyhow::ensure!(
+                message.author.id == ctx.framework().bot_id,
+                "Message is not a backup posted by this bot"
+            );
+            message
+                .attachments
+                .into_iter()
+                .find(|attachment| attachment.filename.ends_with(".json"))
+                .context("Message has no backup attachment")?
+        }
+        _ => anyhow::bail!("Specify either a file or a message link"),
+    };
+
+    let contents = String::from_utf8(attachment.download().await?)?;
+    let (backup, backup_tasks) = storage::parse_backup(&contents, guild_id)?;
+    let tasks = ctx.data().storage.tasks(guild_id)?;
+
+    let embed = diff(&guild, &tasks, &backup, &backup_tasks)?
+        .title(format!("{}を復元しますか？", attachment.filename))
+        .color(Color::DARK_BLUE);
+    let (last_interaction, confirmed) = confirm(ctx, None, embed).await?;
+    if !confirmed {
+        let response = CreateInteractionResponse::UpdateMessage(
+            CreateInteractionResponseMessage::default()
+                .embed(
+                    CreateEmbed::default()
+                        .title("復元をキャンセルしました")
+                        .color(Color::DARK_RED),
+                )
+                .components(vec![]),
+        );
+        last_interaction.create_response(ctx, response).await?;
+        return Ok(());
+    }
+
+    ctx.data().replace_guild(guild_id, backup, &backup_tasks)?;
+    panel::restart_listener(ctx.serenity_context(), ctx.data(), guild_id);
+
+    let response = CreateInteractionResponse::UpdateMessage(
+        CreateInteractionResponseMessage::default()
+            .embed(
+                CreateEmbed::default()
+                    .title("バックアップを復元しました")
+                    .description(format!("タスク: {}件", backup_tasks.len()))
+                    .color(Color::DARK_GREEN),
+            )
+            .components(vec![]),
+    );
+    last_interaction.create_response(ctx, response).await?;
+
+    if let Err(e) = ping::update(&ctx).await {
+        println!("{}: Failed to update ping messages: {:?}", guild_id, e);
+    }
+
+    Ok(())
+}
+
+/// 現在のデータとバックアップの差分を表示します。
+fn diff(
+    guild: &GuildData,
+    tasks: &[Task],
+    backup: &GuildData,
+    backup_tasks: &[Task],
+) -> Result<CreateEmbed, Error> {
+    let current = tasks
+        .iter()
+        .map(|task| (task.id, task))
+        .collect::<BTreeMap<_, _>>();
+    let restored = backup_tasks
+        .iter()
+        .map(|task| (task.id, task))
+        .collect::<BTreeMap<_, _>>();
+
+    let added = restored
+        .iter()
+        .filter(|(id, task)| current.get(id) != Some(task))
+        .map(|(_, task)| *task)
+        .collect::<Vec<_>>();
+    let removed = current
+        .iter()
+        .filter(|(id, task)| restored.get(id) != Some(task))
+        .map(|(_, task)| *task)
+        .collect::<Vec<_>>();
+
+    let serde_json::Value::Object(config) = serde_json::to_value(guild)? else {
+        unreachable!()
+    };
+    let serde_json::Value::Object(backup_config) = serde_json::to_value(backup)? else {
+        unreachable!()
+    };
+    // 送信済みの記録は`Data::replace_guild`が引き継ぐので、変わらない
+    let changed_config = config
+        .iter()
+        .filter(|(key, _)| !["sent_digests", "sent_warnings"].contains(&key.as_str()))
+        .filter(|(key, value)| backup_config.get(*key) != Some(value))
+        .map(|(key, _)| format!("`{}`", key))
+        .collect::<Vec<_>>();
+
+    Ok(CreateEmbed::default().fields(vec![
+        (
+            format!("追加・変更されるタスク ({}件)", added.len()),
+            list_tasks(&added, guild),
+            false,
+        ),
+        (
+            format!("削除・変更されるタスク ({}件)", removed.len()),
+            list_tasks(&removed, guild),
+            false,
+        ),
+        (
+            "変更される設定".into(),
+            if changed_config.is_empty() {
+                "なし".into()
+            } else {
+                changed_config.join(", ")
+            },
+            false,
+        ),
+    ]))
+}
+
+fn list_tasks(tasks: &[&Task], guild: &GuildData) -> String {
+    if tasks.is_empty() {
+        return "なし".into();
+    }
+    let mut list = tasks
+        .iter()
+        .take(MAX_LISTED_TASKS)
+        .map(|task| {
+            format!(
+                "- {} ({})",
+                task.to_field(guild).0,
+                format_datetime(task.datetime, guild.timezone())
+            )
+        })
+        .join("\n");
+    if tasks.len() > MAX_LISTED_TASKS {
+        list += &format!("\n他{}件", tasks.len() - MAX_LISTED_TASKS);
+    }
+    list
+}
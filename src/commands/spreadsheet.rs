@@ -0,0 +1,252 @@
+use anyhow::{Context as _, Error};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use chrono_tz::Tz;
+use poise::serenity_prelude::*;
+use uuid::Uuid;
+
+use crate::{
+    PoiseContext, Subject, Task,
+    interactions::confirm,
+    periodic::ping,
+    utilities::{guild_data, to_utc},
+};
+
+const HEADERS: [&str; 5] = ["種類", "教科", "内容", "日時", "ID"];
+const DATETIME_FORMAT: &str = "%Y/%m/%d %H:%M";
+/// 確認画面に表示するエラーの最大数です。
+const MAX_LISTED_ERRORS: usize = 15;
+/// 埋め込みの説明の最大の文字数です。
+const MAX_DESCRIPTION: usize = 4096;
+/// フィールドの名前の最大の文字数です。タスクの内容はフィールドの名前に表示されます。
+const MAX_FIELD_NAME: usize = 256;
+
+#[poise::command(slash_command, guild_only)]
+/// タスクをCSVファイルとして書き出します。
+pub async fn export_csv(ctx: PoiseContext<'_>) -> Result<(), Error> {
+    let (guild_id, guild) = guild_data(ctx)?;
+    let tz = guild.timezone();
+
+    let tasks = ctx.data().storage.tasks(guild_id)?;
+
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer.write_record(HEADERS)?;
+    for task in &tasks {
+        writer.write_record([
+            guild.category(&task.category).name,
+            match &task.subject {
+                Subject::Set(s) => s.clone(),
+                Subject::Unset => "".into(),
+            },
+            task.details.clone(),
+            task.datetime
+                .with_timezone(&tz)
+                .format(DATETIME_FORMAT)
+                .to_string(),
+            task.id.to_string(),
+        ])?;
+    }
+    // Excelで文字化けしないよう、BOMを付ける
+    let csv = [b"\xEF\xBB\xBF".as_slice(), &writer.into_inner()?].concat();
+
+    ctx.send(
+        poise::CreateReply::default()
+            .embed(
+                CreateEmbed::default()
+                    .title(format!("{}件のタスクを書き出しました", tasks.len()))
+                    .color(Color::DARK_GREEN),
+            )
+            .attachment(CreateAttachment::bytes(csv, "tasks.csv")),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// 列の見出しから、その列の位置を探します。
+fn column(headers: &csv::StringRecord, names: &[&str]) -> Option<usize> {
+    headers
+        .iter()
+        .position(|header| names.contains(&header.trim().to_lowercase().as_str()))
+}
+
+fn parse_datetime(datetime: &str, tz: Tz) -> Result<DateTime<Utc>, String> {
+    let naive = [
+        "%Y/%m/%d %H:%M",
+        "%Y-%m-%d %H:%M",
+        "%Y/%m/%d %H:%M:%S",
+        "%Y-%m-%d %H:%M:%S",
+    ]
+    .iter()
+    .find_map(|format| NaiveDateTime::parse_from_str(datetime, format).ok())
+    .ok_or_else(|| format!("日時「{}」を読み取れません", datetime))?;
+    Ok(to_utc(tz, naive))
+}
+
+/// 確認画面に表示するエラーの一覧です。入りきらない分は「他N件」とします。
+fn format_errors(errors: &[String]) -> String {
+    // 見出しと「他N件」の分を空けておく
+    let limit = MAX_DESCRIPTION - 64;
+    let mut lines = vec![];
+    let mut len = 0;
+    for error in errors.iter().take(MAX_LISTED_ERRORS) {
+        let line_len = error.chars().count() + usize::from(!lines.is_empty());
+        if limit < len + line_len {
+            break;
+        }
+        len += line_len;
+        lines.push(error.clone());
+    }
+    if lines.len() < errors.len() {
+        lines.push(format!("他{}件", errors.len() - lines.len()));
+    }
+    lines.join("\n")
+}
+
+#[poise::command(slash_command, guild_only)]
+/// CSVファイルからタスクを一括で追加します。
+pub async fn import_csv(
+    ctx: PoiseContext<'_>,
+    #[description = "種類・教科・内容・日時の列を持つCSVファイル"] file: Attachment,
+) -> Result<(), Error> {
+    let (guild_id, guild) = guild_data(ctx)?;
+
+    ctx.defer().await?;
+
+    let contents = String::from_utf8(file.download().await?).context("CSV must be UTF-8")?;
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(contents.trim_start_matches('\u{feff}').as_bytes());
+
+    let headers = reader.headers()?.clone();
+    let category_column = column(&headers, &["種類", "category"]).context("No category column")?;
+    let subject_column = column(&headers, &["教科", "subject"]).context("No subject column")?;
+    let details_column = column(&headers, &["内容", "details"]).context("No details column")?;
+    let datetime_column = column(&headers, &["日時", "datetime"]).context("No datetime column")?;
+    let id_column = column(&headers, &["id"]);
+
+    let subjects = guild.subjects.lock().unwrap().clone();
+    let tz = guild.timezone();
+    let existing = ctx.data().storage.tasks(guild_id)?;
+
+    let mut tasks: Vec<Task> = vec![];
+    let mut errors = vec![];
+    for (i, record) in reader.records().enumerate() {
+        // 見出しを1行目として数える
+        let row = i + 2;
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                errors.push(format!("{}行目: {}", row, e));
+                continue;
+            }
+        };
+        let field = |column: usize| record.get(column).unwrap_or_default().trim();
+
+        let task = (|| {
+            let category = field(category_column);
+            let category = guild
+                .find_category(category)
+                .ok_or_else(|| format!("種類「{}」は存在しません", category))?;
+            let subject = match field(subject_column) {
+                "" => Subject::Unset,
+                s if subjects.contains(s) => Subject::Set(s.to_string()),
+                s => return Err(format!("教科「{}」は登録されていません", s)),
+            };
+            let details = field(details_column);
+            if details.is_empty() {
+                return Err("内容が空です".to_string());
+            }
+            let datetime = parse_datetime(field(datetime_column), tz)?;
+            let id = match id_column.map(field).filter(|id| !id.is_empty()) {
+                Some(id) => id
+                    .parse()
+                    .map_err(|_| format!("ID「{}」を読み取れません", id))?,
+                None => Uuid::new_v4(),
+            };
+            if existing.iter().chain(&tasks).any(|task| task.id == id) {
+                return Err("すでに存在するタスクです".to_string());
+            }
+            let task = Task {
+                category,
+                subject,
+                details: details.to_string(),
+                datetime,
+                id,
+            };
+            if MAX_FIELD_NAME < task.to_field(&guild).0.chars().count() {
+                return Err(format!(
+                    "内容が長すぎます (種類と教科を含めて{}文字まで)",
+                    MAX_FIELD_NAME
+                ));
+            }
+            Ok(task)
+        })();
+
+        match task {
+            Ok(task) => tasks.push(task),
+            Err(e) => errors.push(format!("{}行目: {}", row, e)),
+        }
+    }
+
+    let error_list = format_errors(&errors);
+
+    if tasks.is_empty() {
+        ctx.send(
+            poise::CreateReply::default().embed(
+                CreateEmbed::default()
+                    .title("追加できるタスクがありません")
+                    .description(error_list)
+                    .color(Color::RED),
+            ),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let embed = CreateEmbed::default()
+        .title(format!("{}件のタスクを追加しますか？", tasks.len()))
+        .description(if errors.is_empty() {
+            "すべての行を読み取りました".to_string()
+        } else {
+            format!("次の{}行は追加されません\n{}", errors.len(), error_list)
+        })
+        .fields(tasks.iter().take(10).map(|task| task.to_field(&guild)))
+        .color(Color::DARK_BLUE);
+    let (last_interaction, confirmed) = confirm(ctx, None, embed).await?;
+    if !confirmed {
+        let response = CreateInteractionResponse::UpdateMessage(
+            CreateInteractionResponseMessage::default()
+                .embed(
+                    CreateEmbed::default()
+                        .title("追加をキャンセルしました")
+                        .color(Color::DARK_RED),
+                )
+                .components(vec![]),
+        );
+        last_interaction.create_response(ctx, response).await?;
+        return Ok(());
+    }
+
+    for task in &mut tasks {
+        // 確認している間に同じIDのタスクが追加された場合は、新しいIDを振る
+        if ctx.data().storage.task(guild_id, task.id)?.is_some() {
+            task.id = Uuid::new_v4();
+        }
+        ctx.data().add_task(guild_id, ctx.author().id, task)?;
+    }
+
+    let response = CreateInteractionResponse::UpdateMessage(
+        CreateInteractionResponseMessage::default()
+            .embed(
+                CreateEmbed::default()
+                    .title(format!("{}件のタスクを追加しました", tasks.len()))
+                    .color(Color::DARK_GREEN),
+            )
+            .components(vec![]),
+    );
+    last_interaction.create_response(ctx, response).await?;
+
+    ping::update(&ctx).await?;
+
+    Ok(())
+}
@@ -0,0 +1,132 @@
+use anyhow::{Context as _, Error};
+use chrono::{NaiveTime, Weekday};
+use poise::serenity_prelude::*;
+
+use crate::{
+    PoiseContext,
+    data::{self, Period},
+    utilities::{autocomplete_subject, guild_data, parse_weekdays},
+};
+
+fn format_timetable(timetable: &[Period]) -> String {
+    if timetable.is_empty() {
+        return "(なし)".into();
+    }
+    timetable
+        .iter()
+        .map(|period| format!("- {}", period.describe()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn parse_weekday(weekday: &str) -> Result<Weekday, Error> {
+    match parse_weekdays(weekday)?[..] {
+        [weekday] => Ok(weekday),
+        _ => anyhow::bail!("Specify exactly one weekday: {}", weekday),
+    }
+}
+
+fn parse_time(time: &str) -> Result<NaiveTime, Error> {
+    NaiveTime::parse_from_str(time, "%H:%M").with_context(|| format!("Invalid time: {}", time))
+}
+
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD")]
+/// 時間割のコマを追加・上書きします。
+pub async fn set_period(
+    ctx: PoiseContext<'_>,
+    #[description = "曜日 (例: 木)"] weekday: String,
+    #[description = "何限目か"] number: u32,
+    #[description = "開始時刻 (例: 08:40)"] start: String,
+    #[description = "終了時刻 (例: 09:30)"] end: String,
+    #[description = "教科"]
+    #[autocomplete = "autocomplete_subject"]
+    subject: String,
+) -> Result<(), Error> {
+    let (guild_id, guild) = guild_data(ctx)?;
+
+    anyhow::ensure!(
+        guild.subjects.lock().unwrap().contains(&subject),
+        "Subject not found: {}",
+        subject
+    );
+    let period = Period {
+        weekday: parse_weekday(&weekday)?,
+        number,
+        start: parse_time(&start)?,
+        end: parse_time(&end)?,
+        subject,
+    };
+    anyhow::ensure!(period.start < period.end, "Period ends before it starts");
+
+    let description = {
+        let mut timetable = guild.timetable.lock().unwrap();
+        timetable.retain(|p| !(p.weekday == period.weekday && p.number == period.number));
+        timetable.push(period);
+        timetable.sort_by_key(|p| (p.weekday.num_days_from_monday(), p.start));
+        format_timetable(&timetable)
+    };
+    data::save(ctx.data(), guild_id)?;
+
+    ctx.send(
+        poise::CreateReply::default().embed(
+            CreateEmbed::default()
+                .title("時間割を更新しました")
+                .description(description)
+                .color(Color::DARK_BLUE),
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD")]
+/// 時間割のコマを削除します。
+pub async fn remove_period(
+    ctx: PoiseContext<'_>,
+    #[description = "曜日 (例: 木)"] weekday: String,
+    #[description = "何限目か"] number: u32,
+) -> Result<(), Error> {
+    let (guild_id, guild) = guild_data(ctx)?;
+    let weekday = parse_weekday(&weekday)?;
+
+    let description = {
+        let mut timetable = guild.timetable.lock().unwrap();
+        let len = timetable.len();
+        timetable.retain(|p| !(p.weekday == weekday && p.number == number));
+        anyhow::ensure!(timetable.len() < len, "Period not found");
+        format_timetable(&timetable)
+    };
+    data::save(ctx.data(), guild_id)?;
+
+    ctx.send(
+        poise::CreateReply::default().embed(
+            CreateEmbed::default()
+                .title("時間割を更新しました")
+                .description(description)
+                .color(Color::DARK_BLUE),
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only)]
+/// 時間割を表示します。
+pub async fn show_timetable(ctx: PoiseContext<'_>) -> Result<(), Error> {
+    let (_, guild) = guild_data(ctx)?;
+
+    let description = format_timetable(&guild.timetable.lock().unwrap());
+    ctx.send(
+        poise::CreateReply::default().embed(
+            CreateEmbed::default()
+                .title("時間割")
+                .description(description)
+                .color(Color::DARK_BLUE),
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
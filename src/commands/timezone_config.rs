@@ -0,0 +1,54 @@
+use anyhow::{Context as _, Error};
+use chrono::Utc;
+use chrono_tz::Tz;
+use poise::serenity_prelude::*;
+
+use crate::{
+    PoiseContext, data,
+    utilities::{format_datetime, guild_data},
+};
+
+async fn autocomplete_timezone<'a>(_ctx: PoiseContext<'a>, partial: &'a str) -> Vec<String> {
+    let partial = partial.to_lowercase();
+    chrono_tz::TZ_VARIANTS
+        .iter()
+        .map(|tz| tz.name().to_string())
+        .filter(|name| name.to_lowercase().contains(&partial))
+        .take(25)
+        .collect()
+}
+
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD")]
+/// タスクの日時や通知の時刻に使うタイムゾーンを設定します。
+pub async fn set_timezone(
+    ctx: PoiseContext<'_>,
+    #[description = "タイムゾーン (例: Asia/Tokyo)"]
+    #[autocomplete = "autocomplete_timezone"]
+    timezone: String,
+) -> Result<(), Error> {
+    let (guild_id, guild) = guild_data(ctx)?;
+
+    let tz = timezone
+        .parse::<Tz>()
+        .ok()
+        .with_context(|| format!("Unknown timezone: {}", timezone))?;
+
+    guild.timezone.lock().unwrap().replace(tz);
+    data::save(ctx.data(), guild_id)?;
+
+    ctx.send(
+        poise::CreateReply::default().embed(
+            CreateEmbed::default()
+                .title("タイムゾーンを設定しました")
+                .description(format!(
+                    "{} (現在時刻: {})",
+                    tz,
+                    format_datetime(Utc::now(), tz)
+                ))
+                .color(Color::DARK_BLUE),
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
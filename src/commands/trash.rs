@@ -0,0 +1,92 @@
+use anyhow::Error;
+use poise::serenity_prelude::*;
+
+use crate::{PoiseContext, interactions::select_trash, periodic::ping, utilities::guild_data};
+
+#[poise::command(slash_command, guild_only)]
+/// 自分が最後に行ったタスクの追加・削除・編集を取り消します。
+pub async fn undo(ctx: PoiseContext<'_>) -> Result<(), Error> {
+    let (guild_id, guild) = guild_data(ctx)?;
+
+    let Some(change) = ctx.data().undo(guild_id, ctx.author().id)? else {
+        ctx.send(
+            poise::CreateReply::default().embed(
+                CreateEmbed::default()
+                    .title("取り消せる操作がありません")
+                    .color(Color::RED),
+            ),
+        )
+        .await?;
+        return Ok(());
+    };
+
+    ctx.send(
+        poise::CreateReply::default().embed(
+            CreateEmbed::default()
+                .title(format!("タスクの{}を取り消しました", change.kind()))
+                .fields(change.to_fields(&guild))
+                .color(Color::DARK_GREEN),
+        ),
+    )
+    .await?;
+
+    ping::update(&ctx).await?;
+
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only)]
+/// 削除・編集されたタスクをゴミ箱から復元します。
+pub async fn trash(ctx: PoiseContext<'_>) -> Result<(), Error> {
+    let (guild_id, guild) = guild_data(ctx)?;
+
+    let trash = guild
+        .history
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|change| change.before.is_some())
+        .cloned()
+        .collect::<Vec<_>>();
+    if trash.is_empty() {
+        ctx.send(
+            poise::CreateReply::default().embed(
+                CreateEmbed::default()
+                    .title("ゴミ箱は空です")
+                    .color(Color::RED),
+            ),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let (last_interaction, change) = select_trash(
+        ctx,
+        None,
+        Some(
+            CreateEmbed::default()
+                .title("復元するタスクを選択")
+                .color(Color::DARK_BLUE),
+        ),
+        trash,
+    )
+    .await?;
+
+    let task = ctx.data().restore(guild_id, ctx.author().id, change.id)?;
+
+    let response = CreateInteractionResponse::UpdateMessage(
+        CreateInteractionResponseMessage::default()
+            .embed(
+                CreateEmbed::default()
+                    .title("タスクを復元しました")
+                    .fields(vec![task.to_field(&guild)])
+                    .color(Color::DARK_GREEN),
+            )
+            .components(vec![]),
+    );
+    last_interaction.create_response(ctx, response).await?;
+
+    ping::update(&ctx).await?;
+
+    Ok(())
+}
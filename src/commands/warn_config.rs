@@ -1,48 +1,173 @@
 use anyhow::Error;
 use poise::serenity_prelude::*;
 
-use crate::{PoiseContext, data};
+use crate::{
+    PoiseContext, data,
+    data::{GuildData, Subscription},
+    interactions::{select_guild, select_subscription},
+    utilities::format_minutes,
+};
 
 #[poise::command(slash_command, dm_only)]
-/// 宿題の期限接近通知を有効にします。DMで実行してください。
+/// 期限接近通知を有効にします。DMで実行してください。
 pub async fn enable_warn(ctx: PoiseContext<'_>) -> Result<(), Error> {
-    ctx.data()
-        .warn_users
-        .lock()
-        .unwrap()
-        .insert(ctx.author().id);
-    data::save(ctx.data())?;
-
-    ctx.send(
-        poise::CreateReply::default().embed(
+    let (last_interaction, guild_id) = select_guild(
+        ctx,
+        None,
+        Some(
             CreateEmbed::default()
-                .title("期限接近通知を有効にしました")
+                .title("通知を有効にするサーバーを選択してください")
                 .color(Color::DARK_BLUE),
         ),
     )
     .await?;
 
+    // 設定済みの場合はそのまま
+    ctx.data()
+        .guild(guild_id)
+        .subscriptions
+        .lock()
+        .unwrap()
+        .entry(ctx.author().id)
+        .or_default();
+    data::save(ctx.data(), guild_id)?;
+
+    last_interaction
+        .create_response(
+            ctx,
+            CreateInteractionResponse::UpdateMessage(
+                CreateInteractionResponseMessage::default()
+                    .embed(
+                        CreateEmbed::default()
+                            .title("期限接近通知を有効にしました")
+                            .color(Color::DARK_BLUE),
+                    )
+                    .components(vec![]),
+            ),
+        )
+        .await?;
+
     Ok(())
 }
 
 #[poise::command(slash_command, dm_only)]
-/// 宿題の期限接近通知を無効にします。DMで実行してください。
+/// 期限接近通知を無効にします。DMで実行してください。
 pub async fn disable_warn(ctx: PoiseContext<'_>) -> Result<(), Error> {
+    let (last_interaction, guild_id) = select_guild(
+        ctx,
+        None,
+        Some(
+            CreateEmbed::default()
+                .title("通知を無効にするサーバーを選択してください")
+                .color(Color::DARK_BLUE),
+        ),
+    )
+    .await?;
+
     ctx.data()
-        .warn_users
+        .guild(guild_id)
+        .subscriptions
         .lock()
         .unwrap()
         .remove(&ctx.author().id);
-    data::save(ctx.data())?;
+    data::save(ctx.data(), guild_id)?;
 
-    ctx.send(
-        poise::CreateReply::default().embed(
+    last_interaction
+        .create_response(
+            ctx,
+            CreateInteractionResponse::UpdateMessage(
+                CreateInteractionResponseMessage::default()
+                    .embed(
+                        CreateEmbed::default()
+                            .title("期限接近通知を無効にしました")
+                            .color(Color::DARK_BLUE),
+                    )
+                    .components(vec![]),
+            ),
+        )
+        .await?;
+
+    Ok(())
+}
+
+#[poise::command(slash_command, dm_only)]
+/// 期限接近通知の対象とタイミングを設定します。DMで実行してください。
+pub async fn warn_settings(ctx: PoiseContext<'_>) -> Result<(), Error> {
+    let (last_interaction, guild_id) = select_guild(
+        ctx,
+        None,
+        Some(
             CreateEmbed::default()
-                .title("期限接近通知を無効にしました")
+                .title("設定するサーバーを選択してください")
                 .color(Color::DARK_BLUE),
         ),
     )
     .await?;
+    let guild = ctx.data().guild(guild_id);
+    let subjects = guild.subjects.lock().unwrap().clone();
+
+    let (last_interaction, subscription) = select_subscription(
+        ctx,
+        Some(last_interaction),
+        CreateEmbed::default()
+            .title("期限接近通知の設定")
+            .description("選択したそれぞれのタイミングで、タスク1件ごとにDMを送ります")
+            .color(Color::DARK_BLUE),
+        &guild.categories(),
+        &subjects,
+        guild.subscription(ctx.author().id),
+    )
+    .await?;
+
+    guild
+        .subscriptions
+        .lock()
+        .unwrap()
+        .insert(ctx.author().id, subscription.clone());
+    data::save(ctx.data(), guild_id)?;
+
+    last_interaction
+        .create_response(
+            ctx,
+            CreateInteractionResponse::UpdateMessage(
+                CreateInteractionResponseMessage::default()
+                    .embed(
+                        CreateEmbed::default()
+                            .title("期限接近通知を設定しました")
+                            .description(describe(&subscription, &guild))
+                            .color(Color::DARK_BLUE),
+                    )
+                    .components(vec![]),
+            ),
+        )
+        .await?;
 
     Ok(())
 }
+
+fn describe(subscription: &Subscription, guild: &GuildData) -> String {
+    let categories = subscription
+        .categories
+        .iter()
+        .map(|category| guild.category(category).name)
+        .collect::<Vec<_>>()
+        .join("、");
+    let subjects = if subscription.subjects.is_empty() {
+        "すべて".to_string()
+    } else {
+        subscription
+            .subjects
+            .iter()
+            .cloned()
+            .collect::<Vec<_>>()
+            .join("、")
+    };
+    let offsets = subscription
+        .offsets
+        .iter()
+        .rev()
+        .map(|minutes| format!("- 期限の{}前", format_minutes(*minutes)))
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!("種類: {}\n教科: {}\n{}", categories, subjects, offsets)
+}
@@ -0,0 +1,99 @@
+use anyhow::Error;
+use poise::serenity_prelude::*;
+use uuid::Uuid;
+
+use crate::{
+    PoiseContext,
+    data::{self, Webhook},
+    utilities::guild_data,
+};
+
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD")]
+/// タスクの変更を通知するWebhookのURLを追加します。
+pub async fn add_webhook(
+    ctx: PoiseContext<'_>,
+    #[description = "JSONをPOSTするURL"] url: String,
+) -> Result<(), Error> {
+    let (guild_id, guild) = guild_data(ctx)?;
+
+    let parsed = reqwest::Url::parse(&url)?;
+    anyhow::ensure!(
+        matches!(parsed.scheme(), "http" | "https"),
+        "URL must be http or https"
+    );
+
+    let secret = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+    {
+        let mut webhooks = guild.webhooks.lock().unwrap();
+        anyhow::ensure!(
+            webhooks.iter().all(|webhook| webhook.url != url),
+            "Webhook already exists"
+        );
+        webhooks.push(Webhook {
+            url: url.clone(),
+            secret: secret.clone(),
+        });
+    }
+    data::save(ctx.data(), guild_id)?;
+
+    ctx.send(
+        poise::CreateReply::default().ephemeral(true).embed(
+            CreateEmbed::default()
+                .title("Webhookを追加しました")
+                .description(format!(
+                    "{}\n\n署名の秘密鍵: `{}`\n本文のHMAC-SHA256が`X-Task-Bot-Signature`ヘッダーに`sha256=<hex>`の形式で付きます。秘密鍵は再表示できません。",
+                    url, secret
+                ))
+                .color(Color::DARK_BLUE),
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+async fn autocomplete_url<'a>(ctx: PoiseContext<'a>, partial: &'a str) -> Vec<String> {
+    let Ok((_, guild)) = guild_data(ctx) else {
+        return vec![];
+    };
+    guild
+        .webhooks
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|webhook| webhook.url.clone())
+        .filter(|url| url.contains(partial))
+        .take(25)
+        .collect()
+}
+
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD")]
+/// WebhookのURLを削除します。送信待ちの通知も送られなくなります。
+pub async fn remove_webhook(
+    ctx: PoiseContext<'_>,
+    #[description = "削除するURL"]
+    #[autocomplete = "autocomplete_url"]
+    url: String,
+) -> Result<(), Error> {
+    let (guild_id, guild) = guild_data(ctx)?;
+
+    {
+        let mut webhooks = guild.webhooks.lock().unwrap();
+        let len = webhooks.len();
+        webhooks.retain(|webhook| webhook.url != url);
+        anyhow::ensure!(webhooks.len() < len, "Webhook not found");
+    }
+    data::save(ctx.data(), guild_id)?;
+
+    ctx.send(
+        poise::CreateReply::default().ephemeral(true).embed(
+            CreateEmbed::default()
+                .title("Webhookを削除しました")
+                .description(url)
+                .color(Color::DARK_BLUE),
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
@@ -1,56 +1,83 @@
 use std::{
     collections::{BTreeMap, BTreeSet},
     fmt::Display,
-    fs,
-    sync::Mutex,
+    sync::{Arc, Mutex},
 };
 
 use anyhow::{Context, Error};
-use chrono::{DateTime, Local, NaiveDate, NaiveTime, TimeZone};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveTime, Utc, Weekday};
+use chrono_tz::Tz;
 use poise::serenity_prelude::*;
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
-pub enum Category {
-    // イベント
-    Event,
-    // テスト
-    Exam,
-    // 宿題
-    Homework,
-    // 持ち物
-    Belongings,
-    // その他
-    Other,
-}
+use crate::{
+    metrics::Metrics,
+    periodic, recurrence,
+    storage::Storage,
+    utilities::{default_timezone, format_weekday, to_utc},
+    webhook::{self, Payload},
+};
 
-impl From<Category> for String {
-    fn from(category: Category) -> Self {
-        match category {
-            Category::Event => "イベント",
-            Category::Exam => "テスト",
-            Category::Homework => "宿題",
-            Category::Belongings => "持ち物",
-            Category::Other => "その他",
-        }
-        .to_string()
+/// タスクの種類の識別子です。種類の名前を変えても変わりません。
+///
+/// 以前の固定の種類(`Homework`など)は、そのまま既定の種類の識別子になります。
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(transparent)]
+pub struct Category(pub String);
+
+impl Category {
+    /// 新しい種類の識別子を作成します。
+    pub fn generate() -> Self {
+        Self(Uuid::new_v4().simple().to_string())
     }
 }
 
-impl Display for Category {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str(&String::from(*self))
-    }
+/// ギルドごとのタスクの種類の設定です。
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CategoryInfo {
+    pub id: Category,
+    pub name: String,
+    pub emoji: Option<String>,
+    pub color: Option<Color>,
+    /// アーカイブした種類は、新しいタスクに選べません
+    pub archived: bool,
+    /// 期限接近通知の対象にするか
+    pub warn: bool,
+    /// ダイジェストの「明日の持ち物」にまとめるか
+    pub belongings: bool,
 }
 
-impl Category {
-    pub const VALUES: [Category; std::mem::variant_count::<Category>()] = [
-        Category::Event,
-        Category::Exam,
-        Category::Homework,
-        Category::Belongings,
-        Category::Other,
-    ];
+impl CategoryInfo {
+    /// 以前の固定の種類に相当する、既定の種類です。
+    pub fn defaults() -> Vec<Self> {
+        [
+            ("Event", "イベント"),
+            ("Exam", "テスト"),
+            ("Homework", "宿題"),
+            ("Belongings", "持ち物"),
+            ("Other", "その他"),
+        ]
+        .into_iter()
+        .map(|(id, name)| Self {
+            id: Category(id.into()),
+            name: name.into(),
+            emoji: None,
+            color: None,
+            archived: false,
+            warn: true,
+            belongings: id == "Belongings",
+        })
+        .collect()
+    }
+
+    /// `【宿題】`のような、タスクの先頭に付ける表示です。
+    pub fn label(&self) -> String {
+        match &self.emoji {
+            Some(emoji) => format!("{} 【{}】", emoji, self.name),
+            None => format!("【{}】", self.name),
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
@@ -89,55 +116,75 @@ pub struct Task {
     pub category: Category,
     pub subject: Subject,
     pub details: String,
-    pub datetime: DateTime<Local>,
+    pub datetime: DateTime<Utc>,
+    pub id: Uuid,
 }
 
 impl Task {
-    pub fn to_field(&self) -> (String, String, bool) {
-        self.into()
-    }
-
-    pub fn into_partial(self) -> PartialTask {
-        self.into()
-    }
-}
-
-impl From<&Task> for (String, String, bool) {
-    fn from(task: &Task) -> Self {
+    /// 埋め込みのフィールドにします。種類の表示は`guild`の設定に従います。
+    pub fn to_field(&self, guild: &GuildData) -> (String, String, bool) {
         (
             format!(
-                "【{}】{}{}",
-                task.category,
-                match &task.subject {
+                "{}{}{}",
+                guild.category(&self.category).label(),
+                match &self.subject {
                     Subject::Set(s) => format!("{} ", s),
                     Subject::Unset => "".to_string(),
                 },
-                task.details
+                self.details
             ),
             format!(
                 "<t:{}:F>(<t:{}:R>)",
-                task.datetime.timestamp(),
-                task.datetime.timestamp()
+                self.datetime.timestamp(),
+                self.datetime.timestamp()
             ),
             false,
         )
     }
-}
 
-impl From<Task> for PartialTask {
-    fn from(task: Task) -> Self {
-        Self {
-            category: Some(task.category),
-            subject: Some(task.subject),
-            details: Some(task.details),
-            date: Some(task.datetime.date_naive()),
-            time: Some(task.datetime.time()),
+    /// `tz`での日付と時刻に分けて、編集できる形にします。
+    pub fn into_partial(self, tz: Tz) -> PartialTask {
+        let datetime = self.datetime.with_timezone(&tz);
+        PartialTask {
+            id: Some(self.id),
+            category: Some(self.category),
+            subject: Some(self.subject),
+            details: Some(self.details),
+            date: Some(datetime.date_naive()),
+            time: Some(datetime.time()),
         }
     }
 }
 
+/// タスクの絞り込み条件です。`None`の条件は絞り込みません。
+#[derive(Debug, Clone, Default)]
+pub struct TaskFilter {
+    pub category: Option<Category>,
+    pub subject: Option<String>,
+    /// この日時以降のタスクのみ
+    pub from: Option<DateTime<Utc>>,
+    /// この日時より前のタスクのみ
+    pub to: Option<DateTime<Utc>>,
+}
+
+impl TaskFilter {
+    pub fn matches(&self, task: &Task) -> bool {
+        self.category
+            .as_ref()
+            .is_none_or(|category| task.category == *category)
+            && self
+                .subject
+                .as_ref()
+                .is_none_or(|subject| task.subject == Subject::Set(subject.clone()))
+            && self.from.is_none_or(|from| from <= task.datetime)
+            && self.to.is_none_or(|to| task.datetime < to)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Default)]
 pub struct PartialTask {
+    /// 既存のタスクを編集する場合はそのID
+    pub id: Option<Uuid>,
     pub category: Option<Category>,
     pub subject: Option<Subject>,
     pub details: Option<String>,
@@ -146,50 +193,1027 @@ pub struct PartialTask {
 }
 
 impl PartialTask {
-    pub fn unpartial(self) -> Result<Task, Error> {
+    /// `tz`での日付と時刻からタスクを作成します。
+    pub fn unpartial(self, tz: Tz) -> Result<Task, Error> {
         let category = self.category.context("Category not selected")?;
         let subject = self.subject.context("Subject not selected")?;
         let details = self.details.context("Details not selected")?;
         let date = self.date.context("Date not selected")?;
         let time = self.time.context("Time not selected")?;
-        let datetime = Local
-            .from_local_datetime(&date.and_time(time))
-            .single()
-            .context("Invalid date and time")?;
         Ok(Task {
             category,
             subject,
             details,
-            datetime,
+            datetime: to_utc(tz, date.and_time(time)),
+            id: self.id.unwrap_or_else(Uuid::new_v4),
         })
     }
 }
 
+/// タスクの変更の記録です。
+///
+/// `before`が`None`なら追加、`after`が`None`なら削除を表します。
+/// `before`を持つ記録は、ゴミ箱から復元できます。
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Change {
+    pub id: Uuid,
+    pub user: UserId,
+    pub datetime: DateTime<Utc>,
+    pub before: Option<Task>,
+    pub after: Option<Task>,
+    /// ゴミ箱からの復元の場合、復元元の記録です。取り消すとゴミ箱に戻します。
+    #[serde(default)]
+    pub restored: Option<Box<Change>>,
+    /// 繰り返しのタスクの以降の回をまとめて編集した場合、その記録です。
+    #[serde(default)]
+    pub series: Option<SeriesChange>,
+}
+
+/// 繰り返しのタスクを分割して、以降の回をまとめて編集した記録です。
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SeriesChange {
+    /// 分割する前の繰り返しのタスク
+    pub before: RecurringTask,
+    /// 分割した後の繰り返しのタスク。元の繰り返しに回が残らなかった場合は、新しい繰り返しだけです
+    pub after: Vec<RecurringTask>,
+    /// 記録の`before`と`after`のほかに変更した回の、変更前と変更後のタスク
+    pub tasks: Vec<(Task, Option<Task>)>,
+}
+
+impl Change {
+    pub fn new(user: UserId, before: Option<Task>, after: Option<Task>) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            user,
+            datetime: Utc::now(),
+            before,
+            after,
+            restored: None,
+            series: None,
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        if self.restored.is_some() {
+            return "復元";
+        }
+        match (&self.before, &self.after) {
+            (None, _) => "追加",
+            (Some(_), None) => "削除",
+            (Some(_), Some(_)) => "編集",
+        }
+    }
+
+    /// 記録に含まれるタスクと繰り返しのタスクの教科を、復元元の記録のものも含めて返します。
+    pub fn subjects_mut(&mut self) -> Vec<&mut Subject> {
+        let mut subjects = self
+            .before
+            .iter_mut()
+            .chain(&mut self.after)
+            .map(|task| &mut task.subject)
+            .collect::<Vec<_>>();
+        if let Some(series) = &mut self.series {
+            subjects.push(&mut series.before.subject);
+            subjects.extend(series.after.iter_mut().map(|series| &mut series.subject));
+            for (before, after) in &mut series.tasks {
+                subjects.push(&mut before.subject);
+                subjects.extend(after.as_mut().map(|task| &mut task.subject));
+            }
+        }
+        if let Some(restored) = &mut self.restored {
+            subjects.extend(restored.subjects_mut());
+        }
+        subjects
+    }
+
+    pub fn to_fields(&self, guild: &GuildData) -> Vec<(String, String, bool)> {
+        match (&self.before, &self.after) {
+            (Some(before), Some(after)) => vec![
+                before.to_field(guild),
+                ("↓".into(), "".into(), false),
+                after.to_field(guild),
+            ],
+            (Some(task), None) | (None, Some(task)) => vec![task.to_field(guild)],
+            (None, None) => vec![],
+        }
+    }
+}
+
+/// カレンダーの取り込み時に、キーワードから種類や教科を推測する規則です。
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ImportRule {
+    pub keyword: String,
+    pub category: Option<Category>,
+    pub subject: Option<String>,
+}
+
+/// 購読できるカレンダーのフィードです。URLに含まれる`token`で認証します。
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CalendarFeed {
+    pub token: String,
+    pub category: Option<Category>,
+    pub subject: Option<String>,
+}
+
+impl CalendarFeed {
+    pub fn new(category: Option<Category>, subject: Option<String>) -> Self {
+        Self {
+            token: Uuid::new_v4().simple().to_string(),
+            category,
+            subject,
+        }
+    }
+
+    pub fn filter(&self) -> TaskFilter {
+        TaskFilter {
+            category: self.category.clone(),
+            subject: self.subject.clone(),
+            from: None,
+            to: None,
+        }
+    }
+}
+
+/// タスクの変更を通知するWebhookの送信先です。
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Webhook {
+    pub url: String,
+    /// 本文の署名に使う秘密鍵
+    pub secret: String,
+}
+
+/// 送信済みの期限接近通知です。タスクの日時が変わった場合は、もう一度送ります。
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SentWarning {
+    pub user: UserId,
+    pub task: Uuid,
+    pub deadline: DateTime<Utc>,
+    /// 期限の何分前の通知か
+    pub offset: u32,
+}
+
+/// ダイジェストに含めるタスクの範囲です。どちらも送った日の翌日から始まります。
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum DigestWindow {
+    /// 翌日から数えて指定の日数
+    Days(u32),
+    /// 次の指定の曜日まで(その曜日を含む)
+    UntilNext(Weekday),
+}
+
+impl Display for DigestWindow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DigestWindow::Days(1) => write!(f, "明日"),
+            DigestWindow::Days(days) => write!(f, "明日から{}日間", days),
+            DigestWindow::UntilNext(weekday) => {
+                write!(f, "次の{}曜日まで", format_weekday(*weekday))
+            }
+        }
+    }
+}
+
+/// 決まった時刻に、これからのタスクをまとめて送る通知です。
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Digest {
+    /// 埋め込みのタイトル。ギルド内で重複しません
+    pub title: String,
+    pub time: NaiveTime,
+    /// 送る曜日。空の場合は毎日送ります
+    pub weekdays: Vec<Weekday>,
+    pub window: DigestWindow,
+    pub channel: ChannelId,
+    pub role: Option<RoleId>,
+}
+
+impl Digest {
+    /// 以前の設定(毎日12時に明日のタスクを通知)に相当するダイジェストです。
+    pub fn legacy(channel: ChannelId, role: RoleId) -> Self {
+        Self {
+            title: "タスク通知".into(),
+            time: NaiveTime::from_hms_opt(12, 0, 0).unwrap(),
+            weekdays: vec![],
+            window: DigestWindow::Days(1),
+            channel,
+            role: Some(role),
+        }
+    }
+
+    /// `tz`での、`now`以前に最後に送る予定だった日時です。
+    pub fn last(&self, now: DateTime<Utc>, tz: Tz) -> DateTime<Utc> {
+        let today = now.with_timezone(&tz).date_naive();
+        (0..=7)
+            .map(|i| today - Duration::days(i))
+            .filter(|date| self.weekdays.is_empty() || self.weekdays.contains(&date.weekday()))
+            .map(|date| to_utc(tz, date.and_time(self.time)))
+            .find(|datetime| *datetime <= now)
+            .unwrap()
+    }
+
+    /// `sent`に送った場合に含めるタスクの範囲(`from <= datetime < to`)です。
+    pub fn range(&self, sent: DateTime<Utc>, tz: Tz) -> (DateTime<Utc>, DateTime<Utc>) {
+        let today = sent.with_timezone(&tz).date_naive();
+        let days = match self.window {
+            DigestWindow::Days(days) => days as i64,
+            DigestWindow::UntilNext(weekday) => {
+                let days = (weekday.num_days_from_monday() + 7
+                    - today.weekday().num_days_from_monday())
+                    % 7;
+                if days == 0 { 7 } else { days as i64 }
+            }
+        };
+        let from = to_utc(tz, (today + Duration::days(1)).and_time(NaiveTime::MIN));
+        let to = to_utc(
+            tz,
+            (today + Duration::days(days + 1)).and_time(NaiveTime::MIN),
+        );
+        (from, to)
+    }
+
+    pub fn describe(&self) -> String {
+        format!(
+            "{} {} ({}のタスク) → {}{}",
+            if self.weekdays.is_empty() {
+                "毎日".to_string()
+            } else {
+                self.weekdays.iter().map(|w| format_weekday(*w)).collect()
+            },
+            self.time.format("%H:%M"),
+            self.window,
+            self.channel.mention(),
+            self.role
+                .map(|role| format!(" {}", role.mention()))
+                .unwrap_or_default()
+        )
+    }
+}
+
+/// 時間割の1コマです。
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Period {
+    pub weekday: Weekday,
+    /// 何限目か
+    pub number: u32,
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    pub subject: String,
+}
+
+impl Period {
+    pub fn describe(&self) -> String {
+        format!(
+            "{}{}限 {}-{} {}",
+            format_weekday(self.weekday),
+            self.number,
+            self.start.format("%H:%M"),
+            self.end.format("%H:%M"),
+            self.subject
+        )
+    }
+}
+
+/// 常備の持ち物が必要になる日です。
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum BelongingTarget {
+    /// 時間割にその教科がある日
+    Subject(String),
+    Weekday(Weekday),
+}
+
+/// 毎回同じように必要になる持ち物です。ダイジェストの「明日の持ち物」に自動で含めます。
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StandingBelonging {
+    pub item: String,
+    pub target: BelongingTarget,
+}
+
+impl StandingBelonging {
+    pub fn describe(&self) -> String {
+        match &self.target {
+            BelongingTarget::Subject(subject) => {
+                format!("{} ({}の授業がある日)", self.item, subject)
+            }
+            BelongingTarget::Weekday(weekday) => {
+                format!("{} (毎週{}曜日)", self.item, format_weekday(*weekday))
+            }
+        }
+    }
+}
+
+/// 繰り返しのタスクの繰り返し方です。
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Recurrence {
+    /// 毎週、指定の曜日。空の場合は毎日
+    Weekly(Vec<Weekday>),
+    /// 開始日から指定の日数ごと
+    Days(u32),
+    /// 毎月、指定の日。その日がない月は飛ばします
+    Monthly(u32),
+}
+
+impl Display for Recurrence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Recurrence::Weekly(weekdays) if weekdays.is_empty() => write!(f, "毎日"),
+            Recurrence::Weekly(weekdays) => write!(
+                f,
+                "毎週{}",
+                weekdays
+                    .iter()
+                    .map(|w| format_weekday(*w))
+                    .collect::<String>()
+            ),
+            Recurrence::Days(days) => write!(f, "{}日ごと", days),
+            Recurrence::Monthly(day) => write!(f, "毎月{}日", day),
+        }
+    }
+}
+
+/// 繰り返しのタスクです。
+///
+/// 各回は、先の分までふつうのタスクとして作成されるので、1回ずつ編集・削除できます。
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RecurringTask {
+    pub id: Uuid,
+    pub category: Category,
+    pub subject: Subject,
+    pub details: String,
+    pub time: NaiveTime,
+    pub start: NaiveDate,
+    pub recurrence: Recurrence,
+    /// この日を含めて、この日まで繰り返します
+    pub until: Option<NaiveDate>,
+    pub exceptions: BTreeSet<NaiveDate>,
+    /// 作成済みの回の日付と、そのタスクのID。削除された回も残るので、作り直されません
+    pub occurrences: BTreeMap<NaiveDate, Uuid>,
+}
+
+impl RecurringTask {
+    pub fn occurs_on(&self, date: NaiveDate) -> bool {
+        self.start <= date
+            && self.until.is_none_or(|until| date <= until)
+            && !self.exceptions.contains(&date)
+            && match &self.recurrence {
+                Recurrence::Weekly(weekdays) => {
+                    weekdays.is_empty() || weekdays.contains(&date.weekday())
+                }
+                Recurrence::Days(days) => (date - self.start).num_days() % *days as i64 == 0,
+                Recurrence::Monthly(day) => date.day() == *day,
+            }
+    }
+
+    /// `from <= date <= to`の、繰り返す日付です。
+    pub fn dates(&self, from: NaiveDate, to: NaiveDate) -> impl Iterator<Item = NaiveDate> {
+        from.iter_days()
+            .take_while(move |date| *date <= to)
+            .filter(|date| self.occurs_on(*date))
+    }
+
+    /// `date`の回のタスクを、新しいIDで作成します。
+    pub fn task(&self, date: NaiveDate, tz: Tz) -> Task {
+        Task {
+            category: self.category.clone(),
+            subject: self.subject.clone(),
+            details: self.details.clone(),
+            datetime: to_utc(tz, date.and_time(self.time)),
+            id: Uuid::new_v4(),
+        }
+    }
+
+    pub fn describe(&self, guild: &GuildData) -> String {
+        format!(
+            "{}{}{} {} {}{}",
+            guild.category(&self.category).label(),
+            match &self.subject {
+                Subject::Set(s) => format!("{} ", s),
+                Subject::Unset => "".to_string(),
+            },
+            self.details,
+            self.recurrence,
+            self.time.format("%H:%M"),
+            self.until
+                .map(|until| format!(" ({}まで)", until.format("%Y/%m/%d")))
+                .unwrap_or_default()
+        )
+    }
+}
+
+/// ギルドごとの設定です。タスクは`Storage`が保持します。
 #[derive(Serialize, Deserialize, Debug, Default)]
-pub struct Data {
-    pub tasks: Mutex<BTreeSet<Task>>,
+#[serde(default)]
+pub struct GuildData {
     pub subjects: Mutex<BTreeSet<String>>,
     pub suggest_times: Mutex<BTreeMap<NaiveTime, String>>,
     pub panel_message: Mutex<Option<(MessageId, ChannelId)>>,
     pub ping_channel: Mutex<Option<ChannelId>>,
     pub ping_role: Mutex<Option<RoleId>>,
-    pub stop_ping_until: Mutex<DateTime<Local>>,
+    pub stop_ping_until: Mutex<DateTime<Utc>>,
     pub log_channel: Mutex<Option<ChannelId>>,
-    pub warn_users: Mutex<BTreeSet<UserId>>,
+    /// 期限接近通知を受け取るユーザーと、その購読設定
+    pub subscriptions: Mutex<BTreeMap<UserId, Subscription>>,
+    pub sent_warnings: Mutex<BTreeSet<SentWarning>>,
+    /// タスクの変更履歴(古い順)
+    pub history: Mutex<Vec<Change>>,
+    /// 先に追加したものが優先されます
+    pub import_rules: Mutex<Vec<ImportRule>>,
+    pub calendar_feeds: Mutex<Vec<CalendarFeed>>,
+    pub webhooks: Mutex<Vec<Webhook>>,
+    /// `None`の場合は`DEFAULT_TIMEZONE`を使います
+    pub timezone: Mutex<Option<Tz>>,
+    /// 一度も設定していない場合は`None`で、通知チャンネルとロールから`Digest::legacy`を作ります
+    pub digests: Mutex<Option<Vec<Digest>>>,
+    /// 一度も設定していない場合は`None`で、`CategoryInfo::defaults`を使います
+    pub categories: Mutex<Option<Vec<CategoryInfo>>>,
+    /// ダイジェストのタイトルごとの、最後に送った分の予定日時
+    pub sent_digests: Mutex<BTreeMap<String, DateTime<Utc>>>,
+    pub recurring_tasks: Mutex<Vec<RecurringTask>>,
+    /// 曜日と開始時刻の順
+    pub timetable: Mutex<Vec<Period>>,
+    pub standing_belongings: Mutex<Vec<StandingBelonging>>,
+    /// その日だけ不要にした常備の持ち物
+    pub skipped_belongings: Mutex<BTreeSet<(NaiveDate, String)>>,
     #[serde(skip)]
     pub panel_listener: Mutex<Option<tokio::task::JoinHandle<Result<(), Error>>>>,
 }
 
-pub const FILE_PATH: &str = "data.json";
+/// 期限接近通知のタイミングの既定値(分)です。
+pub const DEFAULT_WARN_OFFSETS: [u32; 1] = [60];
+
+/// 期限接近通知で、どのタスクをいつ受け取るかの設定です。
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Subscription {
+    pub categories: BTreeSet<Category>,
+    /// 空の場合はすべての教科。教科が設定されていないタスクは常に対象です
+    pub subjects: BTreeSet<String>,
+    /// 期限の何分前に通知するか
+    pub offsets: BTreeSet<u32>,
+}
+
+impl Default for Subscription {
+    fn default() -> Self {
+        Self {
+            categories: [Category("Homework".into())].into(),
+            subjects: BTreeSet::new(),
+            offsets: DEFAULT_WARN_OFFSETS.into(),
+        }
+    }
+}
+
+impl Subscription {
+    pub fn matches(&self, task: &Task) -> bool {
+        self.categories.contains(&task.category)
+            && match &task.subject {
+                Subject::Set(subject) => {
+                    self.subjects.is_empty() || self.subjects.contains(subject)
+                }
+                Subject::Unset => true,
+            }
+    }
+}
+
+impl GuildData {
+    /// 購読していないユーザーには既定の設定を返します。
+    pub fn subscription(&self, user: UserId) -> Subscription {
+        self.subscriptions
+            .lock()
+            .unwrap()
+            .get(&user)
+            .cloned()
+            .unwrap_or_default()
+    }
 
-pub fn save(data: &Data) -> Result<(), Error> {
-    let data = serde_json::to_string(data)?;
-    fs::write(FILE_PATH, data)?;
+    /// アーカイブしたものも含めた、すべての種類です。
+    pub fn categories(&self) -> Vec<CategoryInfo> {
+        self.categories
+            .lock()
+            .unwrap()
+            .clone()
+            .unwrap_or_else(CategoryInfo::defaults)
+    }
+
+    /// 種類の設定です。見つからない場合は、識別子をそのまま名前にします。
+    pub fn category(&self, id: &Category) -> CategoryInfo {
+        self.categories()
+            .into_iter()
+            .find(|category| category.id == *id)
+            .unwrap_or_else(|| CategoryInfo {
+                id: id.clone(),
+                name: id.0.clone(),
+                emoji: None,
+                color: None,
+                archived: true,
+                warn: false,
+                belongings: false,
+            })
+    }
+
+    /// 名前または識別子から種類を探します。
+    pub fn find_category(&self, name: &str) -> Option<Category> {
+        self.categories()
+            .into_iter()
+            .find(|category| category.name == name || category.id.0 == name)
+            .map(|category| category.id)
+    }
+
+    /// タスクの一覧の並び順です。日付ごとに、種類の並び順、日時の順に並べます。
+    pub fn task_order(&self) -> impl Fn(&Task) -> (NaiveDate, usize, DateTime<Utc>) {
+        let tz = self.timezone();
+        let categories = self.categories();
+        move |task| {
+            let category = categories
+                .iter()
+                .position(|category| category.id == task.category)
+                .unwrap_or(usize::MAX);
+            (
+                task.datetime.with_timezone(&tz).date_naive(),
+                category,
+                task.datetime,
+            )
+        }
+    }
+
+    /// 一覧の先頭のタスクの種類の色です。色が設定されていなければ`default`を返します。
+    pub fn list_color<'a>(
+        &self,
+        tasks: impl IntoIterator<Item = &'a Task>,
+        default: Color,
+    ) -> Color {
+        tasks
+            .into_iter()
+            .next()
+            .and_then(|task| self.category(&task.category).color)
+            .unwrap_or(default)
+    }
+
+    pub fn timezone(&self) -> Tz {
+        self.timezone
+            .lock()
+            .unwrap()
+            .unwrap_or_else(default_timezone)
+    }
+
+    /// `now`より後の`subject`の授業の日付と、そのコマを早い順に返します。
+    pub fn next_classes(
+        &self,
+        subject: &str,
+        now: DateTime<Utc>,
+        count: usize,
+    ) -> Vec<(NaiveDate, Period)> {
+        let tz = self.timezone();
+        let timetable = self.timetable.lock().unwrap();
+        let today = now.with_timezone(&tz).date_naive();
+        today
+            .iter_days()
+            .take(7 * count + 1)
+            .flat_map(|date| {
+                timetable
+                    .iter()
+                    .filter(move |period| period.weekday == date.weekday())
+                    .map(move |period| (date, period))
+            })
+            .filter(|(date, period)| {
+                period.subject == subject && now < to_utc(tz, date.and_time(period.start))
+            })
+            .take(count)
+            .map(|(date, period)| (date, period.clone()))
+            .collect()
+    }
+
+    /// `date`に必要な常備の持ち物です。その日だけ不要にしたものは除きます。
+    pub fn belongings_on(&self, date: NaiveDate) -> Vec<String> {
+        let subjects = self
+            .timetable
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|period| period.weekday == date.weekday())
+            .map(|period| period.subject.clone())
+            .collect::<BTreeSet<_>>();
+        let skipped = self.skipped_belongings.lock().unwrap();
+        let mut belongings = vec![];
+        for belonging in self.standing_belongings.lock().unwrap().iter() {
+            let needed = match &belonging.target {
+                BelongingTarget::Subject(subject) => subjects.contains(subject),
+                BelongingTarget::Weekday(weekday) => *weekday == date.weekday(),
+            };
+            if needed
+                && !skipped.contains(&(date, belonging.item.clone()))
+                && !belongings.contains(&belonging.item)
+            {
+                belongings.push(belonging.item.clone());
+            }
+        }
+        belongings
+    }
+
+    /// 有効なダイジェストの一覧です。
+    pub fn digests(&self) -> Vec<Digest> {
+        if let Some(digests) = &*self.digests.lock().unwrap() {
+            return digests.clone();
+        }
+        match (
+            *self.ping_channel.lock().unwrap(),
+            *self.ping_role.lock().unwrap(),
+        ) {
+            (Some(channel), Some(role)) => vec![Digest::legacy(channel, role)],
+            _ => vec![],
+        }
+    }
+}
+
+pub struct Data {
+    pub guilds: Mutex<BTreeMap<GuildId, Arc<GuildData>>>,
+    pub storage: Arc<dyn Storage>,
+    pub webhook_queue: webhook::Queue,
+    pub metrics: Metrics,
+    /// 予定の時刻に送れなかった通知を、予定から送ってよい時間です
+    pub grace_period: Duration,
+    /// ゴミ箱の記録を保持する期間です
+    pub trash_retention: Duration,
+}
+
+impl Data {
+    pub fn load(storage: Arc<dyn Storage>) -> Result<Self, Error> {
+        let guilds = storage
+            .load_guilds()?
+            .into_iter()
+            .map(|(guild_id, guild)| (guild_id, Arc::new(guild)))
+            .collect();
+        Ok(Self {
+            guilds: Mutex::new(guilds),
+            storage,
+            webhook_queue: webhook::Queue::load()?,
+            metrics: Metrics::default(),
+            grace_period: periodic::grace_period()?,
+            trash_retention: periodic::trash::retention()?,
+        })
+    }
+
+    /// ギルドのデータを取得します。存在しない場合は空のデータを作成します。
+    pub fn guild(&self, guild_id: GuildId) -> Arc<GuildData> {
+        self.guilds
+            .lock()
+            .unwrap()
+            .entry(guild_id)
+            .or_default()
+            .clone()
+    }
+
+    pub fn guild_ids(&self) -> Vec<GuildId> {
+        self.guilds.lock().unwrap().keys().copied().collect()
+    }
+
+    /// トークンからカレンダーのフィードを探します。
+    pub fn calendar_feed(&self, token: &str) -> Option<(GuildId, CalendarFeed)> {
+        self.guilds
+            .lock()
+            .unwrap()
+            .iter()
+            .find_map(|(guild_id, guild)| {
+                guild
+                    .calendar_feeds
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .find(|feed| feed.token == token)
+                    .map(|feed| (*guild_id, feed.clone()))
+            })
+    }
+
+    /// ギルドの設定とタスクをまとめて置き換え、新しい設定を返します。
+    ///
+    /// 置き換える前のパネルのリスナーは停止します。変わったタスクはWebhookで通知します。
+    /// 送った通知をもう一度送らないよう、送信済みの記録は現在のものを引き継ぎます。
+    pub fn replace_guild(
+        &self,
+        guild_id: GuildId,
+        guild: GuildData,
+        tasks: &[Task],
+    ) -> Result<Arc<GuildData>, Error> {
+        let guild = Arc::new(guild);
+        let mut before = self
+            .storage
+            .tasks(guild_id)?
+            .into_iter()
+            .map(|task| (task.id, task))
+            .collect::<BTreeMap<_, _>>();
+        {
+            let mut guilds = self.guilds.lock().unwrap();
+            if let Some(current) = guilds.get(&guild_id) {
+                *guild.sent_digests.lock().unwrap() = current.sent_digests.lock().unwrap().clone();
+                *guild.sent_warnings.lock().unwrap() =
+                    current.sent_warnings.lock().unwrap().clone();
+            }
+            self.storage.replace_guild(guild_id, &guild, tasks)?;
+            if let Some(old) = guilds.insert(guild_id, guild.clone())
+                && let Some(listener) = old.panel_listener.lock().unwrap().take()
+            {
+                listener.abort();
+            }
+        }
+
+        for task in tasks {
+            let old = before.remove(&task.id);
+            if old.as_ref() != Some(task) {
+                self.notify(&Payload::change(guild_id, old, Some(task.clone())));
+            }
+        }
+        for (_, old) in before {
+            self.notify(&Payload::change(guild_id, Some(old), None));
+        }
+        Ok(guild)
+    }
+
+    /// タスクを追加し、履歴に記録します。
+    pub fn add_task(&self, guild_id: GuildId, user: UserId, task: &Task) -> Result<(), Error> {
+        self.storage.insert_task(guild_id, task)?;
+        self.record(guild_id, user, None, Some(task.clone()))
+    }
+
+    /// タスクを削除してゴミ箱に入れ、削除したタスクを返します。
+    pub fn remove_task(&self, guild_id: GuildId, user: UserId, id: Uuid) -> Result<Task, Error> {
+        let task = self.storage.delete_task(guild_id, id)?;
+        self.record(guild_id, user, Some(task.clone()), None)?;
+        Ok(task)
+    }
+
+    /// タスクを置き換えて編集前のタスクをゴミ箱に入れ、編集前のタスクを返します。
+    pub fn edit_task(&self, guild_id: GuildId, user: UserId, task: &Task) -> Result<Task, Error> {
+        let old = self.storage.update_task(guild_id, task)?;
+        self.record(guild_id, user, Some(old.clone()), Some(task.clone()))?;
+        Ok(old)
+    }
+
+    /// `user`の最後の変更を取り消し、取り消した変更を返します。
+    pub fn undo(&self, guild_id: GuildId, user: UserId) -> Result<Option<Change>, Error> {
+        let guild = self.guild(guild_id);
+        let Some(change) = guild
+            .history
+            .lock()
+            .unwrap()
+            .iter()
+            .rfind(|change| change.user == user)
+            .cloned()
+        else {
+            return Ok(None);
+        };
+
+        let current = match change.before.as_ref().or(change.after.as_ref()) {
+            Some(task) => self.storage.task(guild_id, task.id)?,
+            None => None,
+        };
+        // 後から別の変更が加えられたタスクは、その変更を上書きしないよう取り消さない
+        // (追加したタスクがすでに削除されている場合は、取り消し済みとして扱う)
+        anyhow::ensure!(
+            current == change.after || (change.before.is_none() && current.is_none()),
+            "Task has been changed since"
+        );
+
+        if let Some(series) = &change.series {
+            recurrence::revert(self, guild_id, series)?;
+        }
+        match (&change.before, current) {
+            (Some(before), current) => {
+                self.put_task(guild_id, before)?;
+                self.notify(&Payload::change(guild_id, current, Some(before.clone())));
+            }
+            (None, Some(current)) => {
+                self.storage.delete_task(guild_id, current.id)?;
+                self.notify(&Payload::change(guild_id, Some(current), None));
+            }
+            (None, None) => {}
+        }
+
+        {
+            let mut history = guild.history.lock().unwrap();
+            history.retain(|other| other.id != change.id);
+            // 復元を取り消した場合は、復元元の記録をゴミ箱に戻す
+            if let Some(restored) = &change.restored {
+                let i = history.partition_point(|other| other.datetime <= restored.datetime);
+                history.insert(i, *restored.clone());
+            }
+        }
+        save(self, guild_id)?;
+        Ok(Some(change))
+    }
+
+    /// ゴミ箱の中のタスクを復元し、復元したタスクを返します。
+    ///
+    /// 復元もまた変更として履歴に記録されるので、`undo`で取り消せます。
+    pub fn restore(&self, guild_id: GuildId, user: UserId, change_id: Uuid) -> Result<Task, Error> {
+        let guild = self.guild(guild_id);
+        let entry = guild
+            .history
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|change| change.id == change_id)
+            .filter(|change| change.before.is_some())
+            .cloned()
+            .context("Trash entry not found")?;
+        let task = entry.before.clone().unwrap();
+
+        let current = self.put_task(guild_id, &task)?;
+
+        guild
+            .history
+            .lock()
+            .unwrap()
+            .retain(|change| change.id != change_id);
+        self.push_change(
+            guild_id,
+            Change {
+                restored: Some(Box::new(entry)),
+                ..Change::new(user, current, Some(task.clone()))
+            },
+        )?;
+        Ok(task)
+    }
+
+    /// タスクが存在すれば置き換え、存在しなければ追加します。置き換えた場合は以前のタスクを返します。
+    fn put_task(&self, guild_id: GuildId, task: &Task) -> Result<Option<Task>, Error> {
+        if self.storage.task(guild_id, task.id)?.is_some() {
+            Ok(Some(self.storage.update_task(guild_id, task)?))
+        } else {
+            self.storage.insert_task(guild_id, task)?;
+            Ok(None)
+        }
+    }
+
+    fn record(
+        &self,
+        guild_id: GuildId,
+        user: UserId,
+        before: Option<Task>,
+        after: Option<Task>,
+    ) -> Result<(), Error> {
+        self.push_change(guild_id, Change::new(user, before, after))
+    }
+
+    /// 変更を履歴に記録します。通知するのは、変更の`before`と`after`だけです。
+    pub fn push_change(&self, guild_id: GuildId, change: Change) -> Result<(), Error> {
+        let before = change.before.clone();
+        let after = change.after.clone();
+        self.guild(guild_id).history.lock().unwrap().push(change);
+        save(self, guild_id)?;
+        if before.is_none() {
+            self.metrics.tasks_created.inc();
+        }
+        if after.is_none() {
+            self.metrics.tasks_deleted.inc();
+        }
+        self.notify(&Payload::change(guild_id, before, after));
+        Ok(())
+    }
+
+    /// Webhookの送信を予約します。失敗してもタスクの操作は失敗させません。
+    pub fn notify(&self, payload: &Payload) {
+        if let Err(e) = self.webhook_queue.push(self, payload) {
+            println!(
+                "{}: Failed to queue webhook {:?}: {:?}",
+                payload.guild_id, payload.event, e
+            );
+        }
+    }
+}
+
+pub fn save(data: &Data, guild_id: GuildId) -> Result<(), Error> {
+    let start = std::time::Instant::now();
+    data.storage.save_guild(guild_id, &data.guild(guild_id))?;
+    data.metrics.saved(start.elapsed());
     Ok(())
 }
 
-pub fn load() -> Result<Data, Error> {
-    let data = fs::read_to_string(FILE_PATH)?;
-    let data = serde_json::from_str(&data).expect("Failed to parse data.json");
-    Ok(data)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn series(start: NaiveDate, recurrence: Recurrence) -> RecurringTask {
+        RecurringTask {
+            id: Uuid::new_v4(),
+            category: Category("Homework".into()),
+            subject: Subject::Unset,
+            details: "課題".into(),
+            time: NaiveTime::from_hms_opt(8, 40, 0).unwrap(),
+            start,
+            recurrence,
+            until: None,
+            exceptions: BTreeSet::new(),
+            occurrences: BTreeMap::new(),
+        }
+    }
+
+    fn digest(window: DigestWindow) -> Digest {
+        Digest {
+            title: "明日のタスク".into(),
+            time: NaiveTime::from_hms_opt(18, 0, 0).unwrap(),
+            weekdays: vec![],
+            window,
+            channel: ChannelId::new(1),
+            role: None,
+        }
+    }
+
+    #[test]
+    fn range_covers_days_from_tomorrow() {
+        let tz = chrono_tz::Asia::Tokyo;
+        let sent = to_utc(tz, date(2025, 4, 7).and_hms_opt(18, 0, 0).unwrap());
+        assert_eq!(
+            digest(DigestWindow::Days(3)).range(sent, tz),
+            (
+                to_utc(tz, date(2025, 4, 8).and_time(NaiveTime::MIN)),
+                to_utc(tz, date(2025, 4, 11).and_time(NaiveTime::MIN)),
+            )
+        );
+    }
+
+    #[test]
+    fn range_until_next_weekday() {
+        let tz = chrono_tz::Asia::Tokyo;
+        // 2025-04-09は水曜日なので、次の金曜日(4/11)まで
+        let sent = to_utc(tz, date(2025, 4, 9).and_hms_opt(18, 0, 0).unwrap());
+        assert_eq!(
+            digest(DigestWindow::UntilNext(Weekday::Fri)).range(sent, tz),
+            (
+                to_utc(tz, date(2025, 4, 10).and_time(NaiveTime::MIN)),
+                to_utc(tz, date(2025, 4, 12).and_time(NaiveTime::MIN)),
+            )
+        );
+    }
+
+    #[test]
+    fn range_until_next_same_weekday_is_a_week() {
+        let tz = chrono_tz::Asia::Tokyo;
+        // 2025-04-11は金曜日なので、翌週の金曜日(4/18)まで
+        let sent = to_utc(tz, date(2025, 4, 11).and_hms_opt(18, 0, 0).unwrap());
+        assert_eq!(
+            digest(DigestWindow::UntilNext(Weekday::Fri)).range(sent, tz),
+            (
+                to_utc(tz, date(2025, 4, 12).and_time(NaiveTime::MIN)),
+                to_utc(tz, date(2025, 4, 19).and_time(NaiveTime::MIN)),
+            )
+        );
+    }
+
+    #[test]
+    fn weekly_occurs_on_weekdays() {
+        // 2025-04-07は月曜日
+        let series = series(
+            date(2025, 4, 7),
+            Recurrence::Weekly(vec![Weekday::Mon, Weekday::Thu]),
+        );
+        assert!(series.occurs_on(date(2025, 4, 7)));
+        assert!(!series.occurs_on(date(2025, 4, 8)));
+        assert!(series.occurs_on(date(2025, 4, 10)));
+        assert!(!series.occurs_on(date(2025, 3, 31)));
+    }
+
+    #[test]
+    fn interval_counts_from_start() {
+        let series = series(date(2025, 4, 1), Recurrence::Days(3));
+        assert_eq!(
+            series
+                .dates(date(2025, 4, 1), date(2025, 4, 10))
+                .collect::<Vec<_>>(),
+            [
+                date(2025, 4, 1),
+                date(2025, 4, 4),
+                date(2025, 4, 7),
+                date(2025, 4, 10)
+            ]
+        );
+    }
+
+    #[test]
+    fn monthly_skips_missing_days() {
+        let series = series(date(2025, 1, 31), Recurrence::Monthly(31));
+        assert_eq!(
+            series
+                .dates(date(2025, 1, 1), date(2025, 5, 31))
+                .collect::<Vec<_>>(),
+            [date(2025, 1, 31), date(2025, 3, 31), date(2025, 5, 31)]
+        );
+    }
+
+    #[test]
+    fn dates_respect_until_and_exceptions() {
+        let mut series = series(date(2025, 4, 1), Recurrence::Weekly(vec![]));
+        series.until = Some(date(2025, 4, 5));
+        series.exceptions.insert(date(2025, 4, 3));
+        assert_eq!(
+            series
+                .dates(date(2025, 3, 30), date(2025, 4, 30))
+                .collect::<Vec<_>>(),
+            [
+                date(2025, 4, 1),
+                date(2025, 4, 2),
+                date(2025, 4, 4),
+                date(2025, 4, 5)
+            ]
+        );
+    }
 }
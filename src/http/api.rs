@@ -0,0 +1,97 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
+
+use axum::{
+    Json,
+    extract::{Query, State},
+};
+use chrono::{DateTime, Duration, NaiveTime, Utc};
+use chrono_tz::Tz;
+use poise::serenity_prelude::GuildId;
+use serde::Deserialize;
+
+use crate::{
+    Category, Task,
+    data::{Data, TaskFilter},
+    http::{HttpError, resolve_guild},
+    utilities::parse_date,
+};
+
+#[derive(Deserialize)]
+pub struct GuildQuery {
+    guild: Option<GuildId>,
+}
+
+#[derive(Deserialize)]
+pub struct TasksQuery {
+    guild: Option<GuildId>,
+    /// RFC 3339の日時、またはギルドのタイムゾーンでの日付(その日の0時)
+    from: Option<String>,
+    /// RFC 3339の日時、またはギルドのタイムゾーンでの日付(その日を含む)
+    to: Option<String>,
+    category: Option<Category>,
+    subject: Option<String>,
+}
+
+fn parse_datetime(value: &str, end_of_day: bool, tz: Tz) -> Result<DateTime<Utc>, HttpError> {
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(value) {
+        return Ok(datetime.to_utc());
+    }
+    let date = parse_date(value, tz).map_err(|e| HttpError::bad_request(e.to_string()))?;
+    Ok(if end_of_day {
+        date + Duration::days(1)
+    } else {
+        date
+    })
+}
+
+/// `GET /tasks`: 条件に合うタスクを日時順で返します。
+pub async fn tasks(
+    State(data): State<Arc<Data>>,
+    Query(query): Query<TasksQuery>,
+) -> Result<Json<Vec<Task>>, HttpError> {
+    let guild_id = resolve_guild(&data, query.guild)?;
+    let tz = data.guild(guild_id).timezone();
+    let filter = TaskFilter {
+        category: query.category,
+        subject: query.subject,
+        from: query
+            .from
+            .map(|from| parse_datetime(&from, false, tz))
+            .transpose()?,
+        to: query
+            .to
+            .map(|to| parse_datetime(&to, true, tz))
+            .transpose()?,
+    };
+
+    Ok(Json(
+        data.storage
+            .tasks(guild_id)?
+            .into_iter()
+            .filter(|task| filter.matches(task))
+            .collect(),
+    ))
+}
+
+/// `GET /subjects`: 登録されている教科を返します。
+pub async fn subjects(
+    State(data): State<Arc<Data>>,
+    Query(query): Query<GuildQuery>,
+) -> Result<Json<BTreeSet<String>>, HttpError> {
+    let guild_id = resolve_guild(&data, query.guild)?;
+    Ok(Json(data.guild(guild_id).subjects.lock().unwrap().clone()))
+}
+
+/// `GET /suggest_times`: 時刻の候補を、時刻から名前へのマップで返します。
+pub async fn suggest_times(
+    State(data): State<Arc<Data>>,
+    Query(query): Query<GuildQuery>,
+) -> Result<Json<BTreeMap<NaiveTime, String>>, HttpError> {
+    let guild_id = resolve_guild(&data, query.guild)?;
+    Ok(Json(
+        data.guild(guild_id).suggest_times.lock().unwrap().clone(),
+    ))
+}
@@ -0,0 +1,50 @@
+use axum::{
+    extract::{Path, State},
+    http::header,
+    response::IntoResponse,
+};
+
+use crate::{
+    http::{HttpError, HttpState},
+    ics,
+};
+
+/// `GET /calendar/{token}.ics`: 購読用のカレンダーを返します。
+pub async fn calendar(
+    State(state): State<HttpState>,
+    Path(file): Path<String>,
+) -> Result<impl IntoResponse, HttpError> {
+    let token = file.strip_suffix(".ics").unwrap_or(&file);
+    let (guild_id, feed) = state
+        .data
+        .calendar_feed(token)
+        .ok_or_else(|| HttpError::not_found("Unknown calendar"))?;
+
+    let filter = feed.filter();
+    let tasks = state
+        .data
+        .storage
+        .tasks(guild_id)?
+        .into_iter()
+        .filter(|task| filter.matches(task))
+        .collect::<Vec<_>>();
+
+    let mut name = guild_id
+        .name(&state.cache)
+        .unwrap_or_else(|| "task-bot-rs".to_string());
+    let guild = state.data.guild(guild_id);
+    let conditions = feed
+        .category
+        .map(|category| guild.category(&category).name)
+        .into_iter()
+        .chain(feed.subject.clone())
+        .collect::<Vec<_>>();
+    if !conditions.is_empty() {
+        name += &format!(" ({})", conditions.join(" / "));
+    }
+
+    Ok((
+        [(header::CONTENT_TYPE, "text/calendar; charset=utf-8")],
+        ics::export(&name, &tasks, &guild),
+    ))
+}
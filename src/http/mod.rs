@@ -0,0 +1,122 @@
+//! ローカル向けのHTTPサーバーです。
+//!
+//! 環境変数`HTTP_ADDR`(`127.0.0.1:8080`など)が設定されている場合のみ起動します。
+//! カレンダーのフィードと監視用の`/healthz`、`/metrics`以外のエンドポイントは
+//! `Authorization: Bearer <HTTP_TOKEN>`を必要とします。
+
+use std::sync::Arc;
+
+use anyhow::{Context as _, Error};
+use axum::{
+    Json, Router,
+    extract::{FromRef, Request, State},
+    http::{StatusCode, header},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use poise::serenity_prelude::{Cache, GuildId};
+
+use crate::data::Data;
+
+mod api;
+mod feed;
+mod ops;
+
+#[derive(Clone)]
+pub struct HttpState {
+    pub data: Arc<Data>,
+    /// ギルド名の取得に使います
+    pub cache: Arc<Cache>,
+}
+
+impl FromRef<HttpState> for Arc<Data> {
+    fn from_ref(state: &HttpState) -> Self {
+        state.data.clone()
+    }
+}
+
+/// エンドポイントが返すエラーです。本文は`{"error": "..."}`になります。
+pub struct HttpError(StatusCode, String);
+
+impl HttpError {
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self(StatusCode::BAD_REQUEST, message.into())
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self(StatusCode::NOT_FOUND, message.into())
+    }
+}
+
+impl From<Error> for HttpError {
+    fn from(e: Error) -> Self {
+        println!("[http] {:?}", e);
+        Self(StatusCode::INTERNAL_SERVER_ERROR, "Internal error".into())
+    }
+}
+
+impl IntoResponse for HttpError {
+    fn into_response(self) -> Response {
+        (self.0, Json(serde_json::json!({ "error": self.1 }))).into_response()
+    }
+}
+
+/// 指定されたギルドを返します。省略された場合、Botが1つのギルドにのみ参加していればそのギルドを返します。
+pub fn resolve_guild(data: &Data, guild_id: Option<GuildId>) -> Result<GuildId, HttpError> {
+    let guild_ids = data.guild_ids();
+    match guild_id {
+        Some(guild_id) if guild_ids.contains(&guild_id) => Ok(guild_id),
+        Some(guild_id) => Err(HttpError::not_found(format!("Unknown guild {}", guild_id))),
+        None => match guild_ids.as_slice() {
+            [guild_id] => Ok(*guild_id),
+            _ => Err(HttpError::bad_request("guild is required")),
+        },
+    }
+}
+
+async fn authorize(State(token): State<Arc<String>>, request: Request, next: Next) -> Response {
+    let authorized = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|value| value == token.as_str());
+    if authorized {
+        next.run(request).await
+    } else {
+        HttpError(StatusCode::UNAUTHORIZED, "Unauthorized".into()).into_response()
+    }
+}
+
+/// フィードなどの外部に公開するURLの起点です(`HTTP_PUBLIC_URL`)。
+pub fn public_url() -> Result<String, Error> {
+    let url = std::env::var("HTTP_PUBLIC_URL").context("HTTP_PUBLIC_URL is not set")?;
+    Ok(url.trim_end_matches('/').to_string())
+}
+
+/// `HTTP_ADDR`が設定されていれば、HTTPサーバーを起動します。
+pub async fn serve(data: Arc<Data>, cache: Arc<Cache>) -> Result<(), Error> {
+    let Ok(addr) = std::env::var("HTTP_ADDR") else {
+        return Ok(());
+    };
+    let token = std::env::var("HTTP_TOKEN").context("HTTP_TOKEN is required to enable HTTP")?;
+
+    let app = Router::new()
+        .route("/tasks", get(api::tasks))
+        .route("/subjects", get(api::subjects))
+        .route("/suggest_times", get(api::suggest_times))
+        .route_layer(middleware::from_fn_with_state(Arc::new(token), authorize))
+        .route("/calendar/{file}", get(feed::calendar))
+        .route("/healthz", get(ops::healthz))
+        .route("/metrics", get(ops::metrics))
+        .with_state(HttpState { data, cache });
+
+    let listener = tokio::net::TcpListener::bind(&addr)
+        .await
+        .with_context(|| format!("Failed to bind {}", addr))?;
+    println!("[http] Listening on {}", addr);
+    axum::serve(listener, app).await?;
+
+    Ok(())
+}
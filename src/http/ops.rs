@@ -0,0 +1,48 @@
+use std::sync::Arc;
+
+use axum::{Json, extract::State, http::StatusCode, http::header, response::IntoResponse};
+use chrono::{Duration, Utc};
+use serde_json::json;
+
+use crate::data::Data;
+
+/// `GET /healthz`: ゲートウェイへの接続状態と、定期実行が最後に成功した日時を返します。
+///
+/// 接続が切れているか、定期実行が止まっている場合は503を返します。
+pub async fn healthz(State(data): State<Arc<Data>>) -> impl IntoResponse {
+    let metrics = &data.metrics;
+    let gateway = *metrics.gateway.lock().unwrap();
+    let every_day = *metrics.every_day_succeeded.lock().unwrap();
+    let every_minute = *metrics.every_minute_succeeded.lock().unwrap();
+
+    // 起動直後で一度も実行されていない場合は問題としない
+    let now = Utc::now();
+    let stalled = every_day.is_some_and(|last| last < now - Duration::hours(25))
+        || every_minute.is_some_and(|last| last < now - Duration::minutes(5));
+    let healthy = metrics.is_connected() && !stalled;
+
+    (
+        if healthy {
+            StatusCode::OK
+        } else {
+            StatusCode::SERVICE_UNAVAILABLE
+        },
+        Json(json!({
+            "status": if healthy { "ok" } else { "unhealthy" },
+            "gateway": {
+                "connected": metrics.is_connected(),
+                "stage": gateway.map(|stage| format!("{:?}", stage)),
+            },
+            "every_day": { "last_success": every_day },
+            "every_minute": { "last_success": every_minute },
+        })),
+    )
+}
+
+/// `GET /metrics`: Prometheusのテキスト形式でメトリクスを返します。
+pub async fn metrics(State(data): State<Arc<Data>>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        data.metrics.render(),
+    )
+}
@@ -0,0 +1,193 @@
+//! iCalendar(RFC 5545)形式の読み書きを行います。
+
+use anyhow::Error;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use chrono_tz::Tz;
+
+use crate::{Task, data::GuildData, utilities::to_utc};
+
+const DATETIME_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+/// タスクをVEVENTとして含むカレンダーを書き出します。
+pub fn export(name: &str, tasks: &[Task], guild: &GuildData) -> String {
+    let now = Utc::now().format(DATETIME_FORMAT).to_string();
+
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//task-bot-rs//task-bot-rs//JA".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+        format!("X-WR-CALNAME:{}", escape(name)),
+        // 購読したカレンダーアプリに更新を確認する間隔を伝える
+        "REFRESH-INTERVAL;VALUE=DURATION:PT1H".to_string(),
+        "X-PUBLISHED-TTL:PT1H".to_string(),
+    ];
+    for task in tasks {
+        lines.extend([
+            "BEGIN:VEVENT".to_string(),
+            format!("UID:{}@task-bot-rs", task.id),
+            format!("DTSTAMP:{}", now),
+            format!("DTSTART:{}", format_datetime(task.datetime)),
+            format!("SUMMARY:{}", escape(&task.to_field(guild).0)),
+            format!(
+                "CATEGORIES:{}",
+                escape(&guild.category(&task.category).name)
+            ),
+            "END:VEVENT".to_string(),
+        ]);
+    }
+    lines.push("END:VCALENDAR".to_string());
+
+    lines.iter().map(|line| fold(line)).collect()
+}
+
+fn format_datetime(datetime: DateTime<Utc>) -> String {
+    datetime.format(DATETIME_FORMAT).to_string()
+}
+
+/// TEXT型の値をエスケープします。
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// 1行が75オクテットを超えないよう折り返し、CRLFを付けます。
+fn fold(line: &str) -> String {
+    let mut folded = String::new();
+    let mut len = 0;
+    for c in line.chars() {
+        if len + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            len = 1;
+        }
+        folded.push(c);
+        len += c.len_utf8();
+    }
+    folded.push_str("\r\n");
+    folded
+}
+
+/// 読み込んだVEVENTのうち、タスクに使うプロパティです。
+#[derive(Debug, Clone, Default)]
+pub struct Event {
+    pub uid: Option<String>,
+    pub summary: String,
+    pub description: Option<String>,
+    pub categories: Vec<String>,
+    pub start: Option<DateTime<Utc>>,
+}
+
+/// カレンダーに含まれるVEVENTを読み込みます。
+///
+/// UTCでない日時は`TZID`のタイムゾーン、`TZID`がない場合や不明な場合は`tz`で解釈します。
+/// 日時を読み込めないVEVENTは、`start`が`None`になります。
+pub fn parse(contents: &str, tz: Tz) -> Result<Vec<Event>, Error> {
+    let unfolded = contents
+        .replace("\r\n", "\n")
+        .replace("\n ", "")
+        .replace("\n\t", "");
+
+    let mut events = vec![];
+    let mut event: Option<Event> = None;
+    // VEVENTの中にあるVALARMなどの深さ
+    let mut nested = 0;
+    for line in unfolded.lines() {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let mut params = name.split(';');
+        let name = params.next().unwrap_or_default().to_ascii_uppercase();
+        let params = params
+            .filter_map(|param| param.split_once('='))
+            .map(|(key, value)| (key.to_ascii_uppercase(), value.trim_matches('"')))
+            .collect::<Vec<_>>();
+
+        let Some(current) = &mut event else {
+            if name == "BEGIN" && value == "VEVENT" {
+                event = Some(Event::default());
+            }
+            continue;
+        };
+        match name.as_str() {
+            "BEGIN" => nested += 1,
+            "END" if 0 < nested => nested -= 1,
+            "END" if value == "VEVENT" => events.extend(event.take()),
+            _ if 0 < nested => {}
+            "UID" => current.uid = Some(value.to_string()),
+            "SUMMARY" => current.summary = unescape(value),
+            "DESCRIPTION" => current.description = Some(unescape(value)),
+            "CATEGORIES" => current
+                .categories
+                .extend(split_list(value).iter().map(|category| unescape(category))),
+            "DTSTART" => {
+                let is_date = params
+                    .iter()
+                    .any(|(key, value)| key == "VALUE" && *value == "DATE");
+                let tz = params
+                    .iter()
+                    .find(|(key, _)| key == "TZID")
+                    .and_then(|(_, tzid)| tzid.parse().ok())
+                    .unwrap_or(tz);
+                // 読み込めない日時のVEVENTは、日時のないVEVENTとしてスキップさせる
+                current.start = parse_datetime(value, is_date, tz).ok();
+            }
+            _ => {}
+        }
+    }
+
+    Ok(events)
+}
+
+/// DATEまたはDATE-TIME型の値を読み込みます。UTCでない日時は`tz`で解釈します。
+fn parse_datetime(value: &str, is_date: bool, tz: Tz) -> Result<DateTime<Utc>, Error> {
+    let naive = if is_date || value.len() == 8 {
+        NaiveDate::parse_from_str(value, "%Y%m%d")?.and_time(NaiveTime::MIN)
+    } else {
+        NaiveDateTime::parse_from_str(value.trim_end_matches('Z'), "%Y%m%dT%H%M%S")?
+    };
+    if value.ends_with('Z') {
+        Ok(naive.and_utc())
+    } else {
+        Ok(to_utc(tz, naive))
+    }
+}
+
+/// エスケープされていないカンマで区切ります。
+fn split_list(value: &str) -> Vec<&str> {
+    let mut items = vec![];
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            ',' => {
+                items.push(&value[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push(&value[start..]);
+    items
+}
+
+/// TEXT型の値のエスケープを戻します。
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => unescaped.push('\n'),
+            Some(c) => unescaped.push(c),
+            None => {}
+        }
+    }
+    unescaped
+}
@@ -0,0 +1,59 @@
+use anyhow::{Context, Error};
+use chrono::Duration;
+use poise::serenity_prelude::*;
+
+use crate::{PoiseContext, utilities::ResponsiveInteraction};
+
+/// `embed`の内容で実行してよいか確認します。
+pub async fn confirm(
+    ctx: PoiseContext<'_>,
+    interaction: Option<ResponsiveInteraction>,
+    embed: CreateEmbed,
+) -> Result<(ResponsiveInteraction, bool), Error> {
+    const CONFIRM: &str = "confirm";
+    const CANCEL: &str = "cancel";
+
+    let components = vec![CreateActionRow::Buttons(vec![
+        CreateButton::new(CONFIRM)
+            .style(ButtonStyle::Primary)
+            .label("実行する"),
+        CreateButton::new(CANCEL)
+            .style(ButtonStyle::Secondary)
+            .label("キャンセル"),
+    ])];
+
+    let message = if let Some(interaction) = interaction {
+        let response = CreateInteractionResponse::UpdateMessage(
+            CreateInteractionResponseMessage::default()
+                .embed(embed)
+                .components(components),
+        );
+        interaction.create_response(ctx, response).await?;
+        interaction.get_response(ctx).await?
+    } else {
+        ctx.send(
+            poise::CreateReply::default()
+                .embed(embed)
+                .components(components),
+        )
+        .await?
+        .into_message()
+        .await?
+    };
+
+    let interaction = message
+        .await_component_interaction(ctx)
+        .author_id(ctx.author().id)
+        .timeout(Duration::seconds(60 * 30).to_std()?)
+        .await
+        .context("No interaction")?;
+
+    match &interaction.data.kind {
+        ComponentInteractionDataKind::Button => match interaction.data.custom_id.as_str() {
+            CONFIRM => Ok((ResponsiveInteraction::Component(interaction), true)),
+            CANCEL => Ok((ResponsiveInteraction::Component(interaction), false)),
+            _ => unreachable!(),
+        },
+        _ => unreachable!(),
+    }
+}
@@ -1,14 +1,14 @@
 use std::iter;
 
 use anyhow::{Context as _, Error};
-use chrono::{Duration, Local, NaiveDate, NaiveTime};
+use chrono::{Duration, NaiveDate, NaiveTime, Utc};
 use futures::StreamExt;
 use poise::serenity_prelude::*;
 
 use crate::{
-    Category, PartialTask, PoiseContext, Subject, Task,
+    PartialTask, PoiseContext, Subject, Task,
     interactions::{select_date, select_time},
-    utilities::{ResponsiveInteraction, format_date},
+    utilities::{ResponsiveInteraction, format_date, guild_data},
 };
 
 pub async fn create_task(
@@ -23,16 +23,29 @@ pub async fn create_task(
     const TIME: &str = "time";
     const SUBMIT: &str = "submit";
 
-    let subjects = ctx.data().subjects.lock().unwrap().clone();
-    let suggest_times = ctx.data().suggest_times.lock().unwrap().clone();
+    let (_, guild) = guild_data(ctx)?;
+    let subjects = guild.subjects.lock().unwrap().clone();
+    let categories = guild.categories();
+    let suggest_times = guild.suggest_times.lock().unwrap().clone();
+    let tz = guild.timezone();
+    let today = Utc::now().with_timezone(&tz).date_naive();
 
     let components = |task: &PartialTask, submitted: bool| {
         let category_options = CreateSelectMenuKind::String {
-            options: Category::VALUES
+            options: categories
                 .iter()
-                .map(|&c| {
-                    CreateSelectMenuOption::new(c, serde_json::to_string(&c).unwrap())
-                        .default_selection(task.category == Some(c))
+                // アーカイブされた種類は、編集中のタスクの種類である場合のみ表示する
+                .filter(|c| !c.archived || task.category.as_ref() == Some(&c.id))
+                .take(25)
+                .map(|c| {
+                    CreateSelectMenuOption::new(
+                        match &c.emoji {
+                            Some(emoji) => format!("{} {}", emoji, c.name),
+                            None => c.name.clone(),
+                        },
+                        serde_json::to_string(&c.id).unwrap(),
+                    )
+                    .default_selection(task.category.as_ref() == Some(&c.id))
                 })
                 .collect(),
         };
@@ -55,16 +68,39 @@ pub async fn create_task(
                 ))
                 .collect(),
         };
+        // 時間割から、次とその次の授業を日付と時刻の候補にする
+        let classes = match &task.subject {
+            Some(Subject::Set(subject)) => guild.next_classes(subject, Utc::now(), 2),
+            _ => vec![],
+        };
+        let class_selected = classes
+            .iter()
+            .any(|(date, period)| task.date == Some(*date) && task.time == Some(period.start));
         let date_options = CreateSelectMenuKind::String {
-            options: (0..24)
-                .map(|i| {
-                    let date = Local::now().date_naive() + Duration::days(i);
+            options: classes
+                .iter()
+                .zip(["次の授業", "その次の授業"])
+                .map(|((date, period), label)| {
+                    CreateSelectMenuOption::new(
+                        format!("{} ({})", label, period.subject),
+                        serde_json::to_string(&(date, period.start)).unwrap(),
+                    )
+                    .description(format!(
+                        "{} {}限 {}",
+                        format_date(*date),
+                        period.number,
+                        period.start.format("%H:%M")
+                    ))
+                    .default_selection(task.date == Some(*date) && task.time == Some(period.start))
+                })
+                .chain((0..24 - classes.len() as i64).map(|i| {
+                    let date = today + Duration::days(i);
                     CreateSelectMenuOption::new(
                         format_date(date),
                         serde_json::to_string(&Some(date)).unwrap(),
                     )
-                    .default_selection(task.date == Some(date))
-                })
+                    .default_selection(!class_selected && task.date == Some(date))
+                }))
                 .chain(iter::once(
                     CreateSelectMenuOption::new(
                         "その他の日付",
@@ -169,7 +205,12 @@ pub async fn create_task(
                         task.subject.replace(serde_json::from_str(&values[0])?);
                     }
                     DATE => {
-                        task.date = serde_json::from_str(&values[0])?;
+                        if let Ok((date, time)) = serde_json::from_str(&values[0]) {
+                            task.date = Some(date);
+                            task.time = Some(time);
+                        } else {
+                            task.date = serde_json::from_str(&values[0])?;
+                        }
                     }
                     TIME => {
                         task.time = serde_json::from_str(&values[0])?;
@@ -248,7 +289,7 @@ pub async fn create_task(
 
     task.details = Some(inputs[0].clone());
 
-    let task = task.unpartial()?;
+    let task = task.unpartial(tz)?;
 
     Ok((ResponsiveInteraction::Modal(interaction), task))
 }
@@ -8,3 +8,17 @@ mod select_time;
 pub use select_time::select_time;
 mod select_announce;
 pub use select_announce::select_announce;
+mod select_guild;
+pub use select_guild::select_guild;
+mod select_trash;
+pub use select_trash::select_trash;
+mod confirm;
+pub use confirm::confirm;
+mod preview_tasks;
+pub use preview_tasks::preview_tasks;
+mod select_subscription;
+pub use select_subscription::select_subscription;
+mod select_scope;
+pub use select_scope::select_scope;
+mod select_reassign;
+pub use select_reassign::select_reassign;
@@ -0,0 +1,172 @@
+use anyhow::Error;
+use chrono::Duration;
+use futures::StreamExt;
+use poise::serenity_prelude::*;
+
+use crate::{
+    PoiseContext, Task,
+    utilities::{ResponsiveInteraction, format_datetime, guild_data},
+};
+
+const TASKS_PER_PAGE: usize = 10;
+
+/// 追加するタスクを一覧で確認し、追加しないものの選択を外してもらいます。
+///
+/// 選択されたタスクを返します。キャンセルされた場合は`None`を返します。
+pub async fn preview_tasks(
+    ctx: PoiseContext<'_>,
+    interaction: Option<ResponsiveInteraction>,
+    embed: CreateEmbed,
+    tasks: Vec<Task>,
+) -> Result<(ResponsiveInteraction, Option<Vec<Task>>), Error> {
+    const TASKS: &str = "tasks";
+    const PREV: &str = "prev";
+    const NEXT: &str = "next";
+    const SUBMIT: &str = "submit";
+    const CANCEL: &str = "cancel";
+
+    let (_, guild) = guild_data(ctx)?;
+    let tz = guild.timezone();
+
+    let pages = tasks.len().div_ceil(TASKS_PER_PAGE);
+    let page_range =
+        |page: usize| TASKS_PER_PAGE * page..(TASKS_PER_PAGE * (page + 1)).min(tasks.len());
+
+    let embed = |page: usize, selected: &[bool]| {
+        let fields = page_range(page).map(|i| {
+            let (name, value, inline) = tasks[i].to_field(&guild);
+            let mark = if selected[i] { "✅" } else { "❌" };
+            (format!("{} {}", mark, name), value, inline)
+        });
+        embed
+            .clone()
+            .fields(fields)
+            .footer(CreateEmbedFooter::new(format!(
+                "{}/{}件を選択中 ({}/{}ページ)",
+                selected.iter().filter(|s| **s).count(),
+                tasks.len(),
+                page + 1,
+                pages
+            )))
+    };
+
+    let components = |page: usize, selected: &[bool]| {
+        let options = page_range(page)
+            .map(|i| {
+                CreateSelectMenuOption::new(
+                    tasks[i]
+                        .to_field(&guild)
+                        .0
+                        .chars()
+                        .take(100)
+                        .collect::<String>(),
+                    i.to_string(),
+                )
+                .description(format_datetime(tasks[i].datetime, tz))
+                .default_selection(selected[i])
+            })
+            .collect::<Vec<_>>();
+        let max_values = options.len() as u8;
+        let count = selected.iter().filter(|s| **s).count();
+
+        vec![
+            CreateActionRow::SelectMenu(
+                CreateSelectMenu::new(TASKS, CreateSelectMenuKind::String { options })
+                    .placeholder("追加するタスク")
+                    .min_values(0)
+                    .max_values(max_values),
+            ),
+            CreateActionRow::Buttons(vec![
+                CreateButton::new(PREV)
+                    .label("前のページ")
+                    .style(ButtonStyle::Secondary)
+                    .disabled(page == 0),
+                CreateButton::new(NEXT)
+                    .label("次のページ")
+                    .style(ButtonStyle::Secondary)
+                    .disabled(pages <= page + 1),
+            ]),
+            CreateActionRow::Buttons(vec![
+                CreateButton::new(SUBMIT)
+                    .style(ButtonStyle::Primary)
+                    .label(format!("{}件を追加", count))
+                    .disabled(count == 0),
+                CreateButton::new(CANCEL)
+                    .style(ButtonStyle::Secondary)
+                    .label("キャンセル"),
+            ]),
+        ]
+    };
+
+    let mut page = 0;
+    let mut selected = vec![true; tasks.len()];
+    let update = |page: usize, selected: &[bool]| {
+        CreateInteractionResponse::UpdateMessage(
+            CreateInteractionResponseMessage::default()
+                .embed(embed(page, selected))
+                .components(components(page, selected)),
+        )
+    };
+
+    let message = if let Some(interaction) = interaction {
+        interaction
+            .create_response(ctx, update(page, &selected))
+            .await?;
+        interaction.get_response(ctx).await?
+    } else {
+        ctx.send(
+            poise::CreateReply::default()
+                .embed(embed(page, &selected))
+                .components(components(page, &selected)),
+        )
+        .await?
+        .into_message()
+        .await?
+    };
+
+    let mut interaction_stream = message
+        .await_component_interaction(ctx)
+        .author_id(ctx.author().id)
+        .timeout(Duration::seconds(60 * 30).to_std()?)
+        .stream();
+
+    while let Some(interaction) = interaction_stream.next().await {
+        match &interaction.data.kind {
+            ComponentInteractionDataKind::StringSelect { values } => {
+                for i in page_range(page) {
+                    selected[i] = values.contains(&i.to_string());
+                }
+                interaction
+                    .create_response(ctx, update(page, &selected))
+                    .await?;
+            }
+            ComponentInteractionDataKind::Button => match interaction.data.custom_id.as_str() {
+                PREV => {
+                    page = page.saturating_sub(1);
+                    interaction
+                        .create_response(ctx, update(page, &selected))
+                        .await?;
+                }
+                NEXT => {
+                    page += 1;
+                    interaction
+                        .create_response(ctx, update(page, &selected))
+                        .await?;
+                }
+                SUBMIT => {
+                    let tasks = tasks
+                        .into_iter()
+                        .zip(selected)
+                        .filter_map(|(task, selected)| selected.then_some(task))
+                        .collect();
+                    return Ok((ResponsiveInteraction::Component(interaction), Some(tasks)));
+                }
+                CANCEL => return Ok((ResponsiveInteraction::Component(interaction), None)),
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    anyhow::bail!("No interaction")
+}
@@ -1,10 +1,13 @@
 use anyhow::{Context as _, Error};
-use chrono::{Datelike, Duration, Local, NaiveDate};
+use chrono::{Datelike, Duration, NaiveDate, Utc};
 use futures::StreamExt;
 use poise::serenity_prelude::*;
 use serde::{Deserialize, Serialize};
 
-use crate::{utilities::ResponsiveInteraction, PoiseContext};
+use crate::{
+    PoiseContext,
+    utilities::{ResponsiveInteraction, guild_data},
+};
 
 #[derive(Serialize, Deserialize, Clone, Copy)]
 struct MonthHalf {
@@ -36,15 +39,18 @@ pub async fn select_date(
     const DAY: &str = "day";
     const SUBMIT: &str = "submit";
 
+    let (_, guild) = guild_data(ctx)?;
+    let today = Utc::now().with_timezone(&guild.timezone()).date_naive();
+
     let mut last_interaction = None;
-    let mut date = Local::now().date_naive();
+    let mut date = today;
 
     let components = |date: NaiveDate| {
         let month = date.month();
         let is_first_half = date.day() <= 15;
 
         let year_options = CreateSelectMenuKind::String {
-            options: (Local::now().year()..=Local::now().year() + 2)
+            options: (today.year()..=today.year() + 2)
                 .map(|i| {
                     CreateSelectMenuOption::new(i.to_string(), i.to_string())
                         .default_selection(i == date.year())
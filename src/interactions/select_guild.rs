@@ -0,0 +1,114 @@
+use anyhow::{Context as _, Error};
+use chrono::Duration;
+use futures::StreamExt;
+use poise::serenity_prelude::*;
+
+use crate::{PoiseContext, utilities::ResponsiveInteraction};
+
+pub async fn select_guild(
+    ctx: PoiseContext<'_>,
+    interaction: Option<ResponsiveInteraction>,
+    embed: Option<CreateEmbed>,
+) -> Result<(ResponsiveInteraction, GuildId), Error> {
+    const GUILD: &str = "guild";
+    const SUBMIT: &str = "submit";
+
+    // Botが管理しているギルドのうち、実行したユーザーが参加しているもの
+    let mut guilds = vec![];
+    for guild_id in ctx.data().guild_ids() {
+        if guild_id.member(ctx, ctx.author().id).await.is_ok() {
+            let name = guild_id.name(ctx).unwrap_or(guild_id.to_string());
+            guilds.push((guild_id, name));
+        }
+    }
+    // 選択肢が空のセレクトメニューは送信できない
+    anyhow::ensure!(
+        !guilds.is_empty(),
+        "No servers managed by this bot are shared with you"
+    );
+    // セレクトメニューの選択肢は25個まで
+    guilds.truncate(25);
+
+    let components = |selected_guild: Option<GuildId>| {
+        let guild_options = CreateSelectMenuKind::String {
+            options: guilds
+                .iter()
+                .map(|(id, name)| {
+                    CreateSelectMenuOption::new(name, id.to_string())
+                        .default_selection(selected_guild == Some(*id))
+                })
+                .collect(),
+        };
+
+        vec![
+            CreateActionRow::SelectMenu(
+                CreateSelectMenu::new(GUILD, guild_options).placeholder("サーバー"),
+            ),
+            CreateActionRow::Buttons(vec![
+                CreateButton::new(SUBMIT)
+                    .style(ButtonStyle::Primary)
+                    .label("送信")
+                    .disabled(selected_guild.is_none()),
+            ]),
+        ]
+    };
+
+    let mut guild = None;
+
+    let message = if let Some(interaction) = interaction {
+        let response = CreateInteractionResponse::UpdateMessage(
+            if let Some(embed) = embed {
+                CreateInteractionResponseMessage::default().embed(embed)
+            } else {
+                CreateInteractionResponseMessage::default()
+            }
+            .components(components(guild)),
+        );
+        interaction.create_response(ctx, response).await?;
+        interaction.get_response(ctx).await?
+    } else {
+        ctx.send(
+            if let Some(embed) = embed {
+                poise::CreateReply::default().embed(embed)
+            } else {
+                poise::CreateReply::default()
+            }
+            .components(components(guild)),
+        )
+        .await?
+        .into_message()
+        .await?
+    };
+
+    let mut interaction_stream = message
+        .await_component_interaction(ctx)
+        .timeout(Duration::seconds(60 * 30).to_std()?)
+        .stream();
+
+    let mut last_interaction = None;
+    while let Some(interaction) = interaction_stream.next().await {
+        match &interaction.data.kind {
+            ComponentInteractionDataKind::StringSelect { values } => {
+                if interaction.data.custom_id == GUILD {
+                    guild.replace(values[0].parse()?);
+                }
+                let response = CreateInteractionResponse::UpdateMessage(
+                    CreateInteractionResponseMessage::default().components(components(guild)),
+                );
+                interaction.create_response(ctx, response).await?;
+            }
+            ComponentInteractionDataKind::Button => {
+                if interaction.data.custom_id == SUBMIT {
+                    last_interaction.replace(interaction);
+                    break;
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    Ok((
+        ResponsiveInteraction::Component(last_interaction.context("No interaction")?),
+        guild.context("Guild not selected")?,
+    ))
+}
@@ -0,0 +1,67 @@
+use anyhow::{Context, Error};
+use chrono::Duration;
+use poise::serenity_prelude::*;
+
+use crate::{PoiseContext, utilities::ResponsiveInteraction};
+
+/// 削除する教科のタスクを、どの教科に付け替えるか選んでもらいます。`None`の場合は教科なしにします。
+pub async fn select_reassign(
+    ctx: PoiseContext<'_>,
+    interaction: ResponsiveInteraction,
+    subject: &str,
+    count: usize,
+    subjects: &[String],
+) -> Result<(ResponsiveInteraction, Option<String>), Error> {
+    const SUBJECT: &str = "subject";
+    const UNSET: &str = "unset";
+
+    let embed = CreateEmbed::default()
+        .title(format!("{}のタスクが{}件あります", subject, count))
+        .description("付け替える教科を選ぶか、教科を指定しないタスクにしてください")
+        .color(Color::DARK_BLUE);
+    let mut components = vec![];
+    // セレクトメニューの選択肢は25個まで
+    let options = subjects
+        .iter()
+        .filter(|s| *s != subject)
+        .take(25)
+        .map(|s| CreateSelectMenuOption::new(s, s))
+        .collect::<Vec<_>>();
+    if !options.is_empty() {
+        components.push(CreateActionRow::SelectMenu(
+            CreateSelectMenu::new(SUBJECT, CreateSelectMenuKind::String { options })
+                .placeholder("付け替える教科"),
+        ));
+    }
+    components.push(CreateActionRow::Buttons(vec![
+        CreateButton::new(UNSET)
+            .style(ButtonStyle::Secondary)
+            .label("教科を指定しない"),
+    ]));
+
+    let response = CreateInteractionResponse::UpdateMessage(
+        CreateInteractionResponseMessage::default()
+            .embed(embed)
+            .components(components),
+    );
+    interaction.create_response(ctx, response).await?;
+    let message = interaction.get_response(ctx).await?;
+
+    let interaction = message
+        .await_component_interaction(ctx)
+        .author_id(ctx.author().id)
+        .timeout(Duration::seconds(60 * 30).to_std()?)
+        .await
+        .context("No interaction")?;
+
+    match &interaction.data.kind {
+        ComponentInteractionDataKind::StringSelect { values } => {
+            let subject = values[0].clone();
+            Ok((ResponsiveInteraction::Component(interaction), Some(subject)))
+        }
+        ComponentInteractionDataKind::Button => {
+            Ok((ResponsiveInteraction::Component(interaction), None))
+        }
+        _ => unreachable!(),
+    }
+}
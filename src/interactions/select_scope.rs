@@ -0,0 +1,62 @@
+use anyhow::{Context, Error};
+use chrono::Duration;
+use poise::serenity_prelude::*;
+
+use crate::{PoiseContext, utilities::ResponsiveInteraction};
+
+/// 繰り返しのタスクの回を編集するとき、それ以降の回にも反映するか選んでもらいます。
+pub async fn select_scope(
+    ctx: PoiseContext<'_>,
+    interaction: Option<ResponsiveInteraction>,
+) -> Result<(ResponsiveInteraction, bool), Error> {
+    const THIS: &str = "this";
+    const FOLLOWING: &str = "following";
+
+    let embed = CreateEmbed::default()
+        .title("繰り返しのタスクです")
+        .description("変更をどの回に反映しますか？")
+        .color(Color::DARK_BLUE);
+    let components = vec![CreateActionRow::Buttons(vec![
+        CreateButton::new(THIS)
+            .style(ButtonStyle::Primary)
+            .label("この回のみ"),
+        CreateButton::new(FOLLOWING)
+            .style(ButtonStyle::Secondary)
+            .label("この回以降すべて"),
+    ])];
+
+    let message = if let Some(interaction) = interaction {
+        let response = CreateInteractionResponse::UpdateMessage(
+            CreateInteractionResponseMessage::default()
+                .embed(embed)
+                .components(components),
+        );
+        interaction.create_response(ctx, response).await?;
+        interaction.get_response(ctx).await?
+    } else {
+        ctx.send(
+            poise::CreateReply::default()
+                .embed(embed)
+                .components(components),
+        )
+        .await?
+        .into_message()
+        .await?
+    };
+
+    let interaction = message
+        .await_component_interaction(ctx)
+        .author_id(ctx.author().id)
+        .timeout(Duration::seconds(60 * 30).to_std()?)
+        .await
+        .context("No interaction")?;
+
+    match &interaction.data.kind {
+        ComponentInteractionDataKind::Button => match interaction.data.custom_id.as_str() {
+            THIS => Ok((ResponsiveInteraction::Component(interaction), false)),
+            FOLLOWING => Ok((ResponsiveInteraction::Component(interaction), true)),
+            _ => unreachable!(),
+        },
+        _ => unreachable!(),
+    }
+}
@@ -0,0 +1,196 @@
+use std::collections::BTreeSet;
+
+use anyhow::Error;
+use chrono::Duration;
+use futures::StreamExt;
+use poise::serenity_prelude::*;
+
+use crate::{
+    Category, PoiseContext,
+    data::{CategoryInfo, Subscription},
+    utilities::{ResponsiveInteraction, format_minutes},
+};
+
+/// 選択できる期限接近通知のタイミング(分)です。
+const PRESETS: [u32; 11] = [
+    10,
+    30,
+    60,
+    2 * 60,
+    3 * 60,
+    6 * 60,
+    12 * 60,
+    24 * 60,
+    2 * 24 * 60,
+    3 * 24 * 60,
+    7 * 24 * 60,
+];
+
+/// 期限接近通知で受け取るタスクの種類と教科、通知のタイミングを選択してもらいます。
+pub async fn select_subscription(
+    ctx: PoiseContext<'_>,
+    interaction: Option<ResponsiveInteraction>,
+    embed: CreateEmbed,
+    categories: &[CategoryInfo],
+    subjects: &BTreeSet<String>,
+    current: Subscription,
+) -> Result<(ResponsiveInteraction, Subscription), Error> {
+    const CATEGORIES: &str = "categories";
+    const SUBJECTS: &str = "subjects";
+    const OFFSETS: &str = "offsets";
+    const SUBMIT: &str = "submit";
+
+    // セレクトメニューの選択肢は25個まで
+    let categories = categories
+        .iter()
+        .filter(|category| !category.archived && category.warn)
+        .take(25)
+        .collect::<Vec<_>>();
+    anyhow::ensure!(!categories.is_empty(), "No categories to subscribe");
+
+    let mut selected = current;
+    // 選択肢にない種類や教科(削除された教科など)は残さない
+    selected
+        .categories
+        .retain(|id| categories.iter().any(|category| category.id == *id));
+    selected
+        .subjects
+        .retain(|subject| subjects.contains(subject));
+    // 選択中の教科を先に表示する。表示しきれなかった教科の選択はそのまま残す
+    let subjects = selected
+        .subjects
+        .iter()
+        .chain(
+            subjects
+                .iter()
+                .filter(|subject| !selected.subjects.contains(*subject)),
+        )
+        .take(25)
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let components = |selected: &Subscription| {
+        let category_options = categories
+            .iter()
+            .map(|category| {
+                CreateSelectMenuOption::new(&category.name, &category.id.0)
+                    .default_selection(selected.categories.contains(&category.id))
+            })
+            .collect();
+        let offsets = PRESETS
+            .iter()
+            .map(|&minutes| {
+                CreateSelectMenuOption::new(
+                    format!("{}前", format_minutes(minutes)),
+                    minutes.to_string(),
+                )
+                .default_selection(selected.offsets.contains(&minutes))
+            })
+            .collect();
+
+        let mut components = vec![CreateActionRow::SelectMenu(
+            CreateSelectMenu::new(
+                CATEGORIES,
+                CreateSelectMenuKind::String {
+                    options: category_options,
+                },
+            )
+            .placeholder("通知する種類")
+            .min_values(1)
+            .max_values(categories.len() as u8),
+        )];
+        if !subjects.is_empty() {
+            let options = subjects
+                .iter()
+                .map(|subject| {
+                    CreateSelectMenuOption::new(subject, subject)
+                        .default_selection(selected.subjects.contains(subject))
+                })
+                .collect();
+            components.push(CreateActionRow::SelectMenu(
+                CreateSelectMenu::new(SUBJECTS, CreateSelectMenuKind::String { options })
+                    .placeholder("通知する教科(未選択ですべて)")
+                    .min_values(0)
+                    .max_values(subjects.len() as u8),
+            ));
+        }
+        components.extend([
+            CreateActionRow::SelectMenu(
+                CreateSelectMenu::new(OFFSETS, CreateSelectMenuKind::String { options: offsets })
+                    .placeholder("通知のタイミング")
+                    .min_values(1)
+                    .max_values(PRESETS.len() as u8),
+            ),
+            CreateActionRow::Buttons(vec![
+                CreateButton::new(SUBMIT)
+                    .style(ButtonStyle::Primary)
+                    .label("保存")
+                    .disabled(selected.categories.is_empty() || selected.offsets.is_empty()),
+            ]),
+        ]);
+        components
+    };
+
+    let message = if let Some(interaction) = interaction {
+        let response = CreateInteractionResponse::UpdateMessage(
+            CreateInteractionResponseMessage::default()
+                .embed(embed)
+                .components(components(&selected)),
+        );
+        interaction.create_response(ctx, response).await?;
+        interaction.get_response(ctx).await?
+    } else {
+        ctx.send(
+            poise::CreateReply::default()
+                .embed(embed)
+                .components(components(&selected)),
+        )
+        .await?
+        .into_message()
+        .await?
+    };
+
+    let mut interaction_stream = message
+        .await_component_interaction(ctx)
+        .author_id(ctx.author().id)
+        .timeout(Duration::seconds(60 * 30).to_std()?)
+        .stream();
+
+    while let Some(interaction) = interaction_stream.next().await {
+        match &interaction.data.kind {
+            ComponentInteractionDataKind::StringSelect { values } => {
+                match interaction.data.custom_id.as_str() {
+                    CATEGORIES => {
+                        selected.categories =
+                            values.iter().map(|value| Category(value.clone())).collect();
+                    }
+                    SUBJECTS => {
+                        selected
+                            .subjects
+                            .retain(|subject| !subjects.contains(subject));
+                        selected.subjects.extend(values.iter().cloned());
+                    }
+                    OFFSETS => {
+                        selected.offsets = values
+                            .iter()
+                            .map(|value| value.parse())
+                            .collect::<Result<_, _>>()?;
+                    }
+                    _ => unreachable!(),
+                }
+                let response = CreateInteractionResponse::UpdateMessage(
+                    CreateInteractionResponseMessage::default().components(components(&selected)),
+                );
+                interaction.create_response(ctx, response).await?;
+            }
+            ComponentInteractionDataKind::Button => {
+                if interaction.data.custom_id == SUBMIT {
+                    return Ok((ResponsiveInteraction::Component(interaction), selected));
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    anyhow::bail!("No interaction")
+}
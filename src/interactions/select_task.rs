@@ -3,6 +3,7 @@ use chrono::Duration;
 use futures::StreamExt;
 use itertools::Itertools;
 use poise::serenity_prelude::*;
+use uuid::Uuid;
 
 use crate::{
     utilities::{format_datetime, ResponsiveInteraction},
@@ -19,21 +20,24 @@ pub async fn select_task(
     const PREV: &str = "prev";
     const NEXT: &str = "next";
 
+    let guild_id = ctx.guild_id().context("Not in a guild")?;
+    let tasks = ctx.data().storage.tasks(guild_id)?;
+    let guild = ctx.data().guild(guild_id);
+    let tz = guild.timezone();
+
     let mut page = 0;
+    let order = guild.task_order();
     let components = |page: usize, selected_task: &Option<Task>| {
-        let options = ctx
-            .data()
-            .tasks
-            .lock()
-            .unwrap()
+        let options = tasks
             .iter()
-            .enumerate()
-            .sorted_by_key(|(_, task)| task.datetime)
+            .sorted_by_key(|task| order(task))
             .rev()
-            .map(|(idx, task)| {
-                CreateSelectMenuOption::new(task.to_field().0, idx.to_string())
-                    .description(format_datetime(task.datetime))
-                    .default_selection(selected_task.as_ref() == Some(task))
+            .map(|task| {
+                CreateSelectMenuOption::new(task.to_field(&guild).0, task.id.to_string())
+                    .description(format_datetime(task.datetime, tz))
+                    .default_selection(
+                        selected_task.as_ref().map(|selected| selected.id) == Some(task.id),
+                    )
             })
             .skip(25 * page)
             .collect::<Vec<_>>();
@@ -102,12 +106,13 @@ pub async fn select_task(
         match &interaction.data.kind {
             ComponentInteractionDataKind::StringSelect { values } => {
                 if interaction.data.custom_id == TASK {
-                    let tasks = ctx.data().tasks.lock().unwrap().clone();
+                    let id = values[0].parse::<Uuid>()?;
                     task.replace(
                         tasks
-                            .into_iter()
-                            .nth(values[0].parse::<usize>().unwrap())
-                            .context("Invalid task")?,
+                            .iter()
+                            .find(|task| task.id == id)
+                            .context("Invalid task")?
+                            .clone(),
                     );
                 }
                 let response = CreateInteractionResponse::UpdateMessage(
@@ -0,0 +1,164 @@
+use anyhow::{Context as _, Error};
+use chrono::Duration;
+use futures::StreamExt;
+use poise::serenity_prelude::*;
+use uuid::Uuid;
+
+use crate::{
+    PoiseContext,
+    data::Change,
+    utilities::{ResponsiveInteraction, format_datetime, guild_data},
+};
+
+/// ゴミ箱の中から、復元する記録を選択します。
+pub async fn select_trash(
+    ctx: PoiseContext<'_>,
+    interaction: Option<ResponsiveInteraction>,
+    embed: Option<CreateEmbed>,
+    trash: Vec<Change>,
+) -> Result<(ResponsiveInteraction, Change), Error> {
+    const ENTRY: &str = "entry";
+    const SUBMIT: &str = "submit";
+    const PREV: &str = "prev";
+    const NEXT: &str = "next";
+
+    let (_, guild) = guild_data(ctx)?;
+    let tz = guild.timezone();
+
+    let mut page = 0;
+    let components = |page: usize, selected: &Option<Change>| {
+        let options = trash
+            .iter()
+            .rev()
+            .filter_map(|change| {
+                let task = change.before.as_ref()?;
+                Some(
+                    CreateSelectMenuOption::new(task.to_field(&guild).0, change.id.to_string())
+                        .description(format!(
+                            "{} ({})",
+                            change.kind(),
+                            format_datetime(change.datetime, tz)
+                        ))
+                        .default_selection(
+                            selected.as_ref().map(|selected| selected.id) == Some(change.id),
+                        ),
+                )
+            })
+            .skip(25 * page)
+            .collect::<Vec<_>>();
+        let entry_options = CreateSelectMenuKind::String {
+            options: options.clone().into_iter().take(25).collect(),
+        };
+
+        vec![
+            CreateActionRow::SelectMenu(
+                CreateSelectMenu::new(ENTRY, entry_options).placeholder("復元するタスク"),
+            ),
+            CreateActionRow::Buttons(vec![
+                CreateButton::new(PREV)
+                    .label("前のページ")
+                    .style(ButtonStyle::Secondary)
+                    .disabled(page == 0),
+                CreateButton::new(NEXT)
+                    .label("次のページ")
+                    .style(ButtonStyle::Secondary)
+                    .disabled(options.len() <= 25),
+            ]),
+            CreateActionRow::Buttons(vec![
+                CreateButton::new(SUBMIT)
+                    .style(ButtonStyle::Primary)
+                    .label("復元")
+                    .disabled(selected.is_none()),
+            ]),
+        ]
+    };
+
+    let message = if let Some(interaction) = interaction {
+        interaction
+            .create_response(
+                ctx,
+                CreateInteractionResponse::UpdateMessage(
+                    if let Some(embed) = embed {
+                        CreateInteractionResponseMessage::default().embed(embed)
+                    } else {
+                        CreateInteractionResponseMessage::default()
+                    }
+                    .components(components(page, &None)),
+                ),
+            )
+            .await?;
+        interaction.get_response(ctx).await?
+    } else {
+        ctx.send(
+            if let Some(embed) = embed {
+                poise::CreateReply::default().embed(embed)
+            } else {
+                poise::CreateReply::default()
+            }
+            .components(components(page, &None)),
+        )
+        .await?
+        .into_message()
+        .await?
+    };
+
+    let mut interaction_stream = message
+        .await_component_interaction(ctx)
+        .timeout(Duration::seconds(60 * 30).to_std()?)
+        .stream();
+
+    let mut selected: Option<Change> = None;
+    let mut last_interaction = None;
+    while let Some(interaction) = interaction_stream.next().await {
+        match &interaction.data.kind {
+            ComponentInteractionDataKind::StringSelect { values } => {
+                if interaction.data.custom_id == ENTRY {
+                    let id = values[0].parse::<Uuid>()?;
+                    selected.replace(
+                        trash
+                            .iter()
+                            .find(|change| change.id == id)
+                            .context("Invalid trash entry")?
+                            .clone(),
+                    );
+                }
+                let response = CreateInteractionResponse::UpdateMessage(
+                    CreateInteractionResponseMessage::default()
+                        .components(components(page, &selected)),
+                );
+                interaction.create_response(&ctx, response).await?;
+            }
+            ComponentInteractionDataKind::Button => match interaction.data.custom_id.as_str() {
+                PREV => {
+                    page = page.saturating_sub(1);
+                    selected = None;
+                    let response = CreateInteractionResponse::UpdateMessage(
+                        CreateInteractionResponseMessage::default()
+                            .components(components(page, &selected)),
+                    );
+                    interaction.create_response(ctx, response).await?;
+                }
+                NEXT => {
+                    page += 1;
+                    selected = None;
+                    let response = CreateInteractionResponse::UpdateMessage(
+                        CreateInteractionResponseMessage::default()
+                            .components(components(page, &selected)),
+                    );
+                    interaction.create_response(ctx, response).await?;
+                }
+                SUBMIT => {
+                    last_interaction.replace(interaction);
+                    break;
+                }
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    Ok((
+        ResponsiveInteraction::Component(last_interaction.context("No interaction")?),
+        selected.context("Trash entry not selected")?,
+    ))
+}
@@ -1,4 +1,4 @@
-#![feature(variant_count)]
+use std::sync::Arc;
 
 use anyhow::Error;
 use data::{Category, Data, PartialTask, Subject, Task};
@@ -7,48 +7,37 @@ use poise::serenity_prelude::*;
 
 mod commands;
 mod data;
+mod http;
+mod ics;
 mod interactions;
+mod metrics;
 mod periodic;
+mod recurrence;
+mod storage;
+mod subjects;
 mod utilities;
+mod webhook;
 
-pub type PoiseContext<'a> = poise::Context<'a, Data, Error>;
+pub type PoiseContext<'a> = poise::Context<'a, Arc<Data>, Error>;
 
 async fn event_handler(
     ctx: &Context,
     event: &FullEvent,
-    _framework: poise::FrameworkContext<'_, Data, Error>,
-    data: &Data,
+    _framework: poise::FrameworkContext<'_, Arc<Data>, Error>,
+    data: &Arc<Data>,
 ) -> Result<(), Error> {
     match event {
         FullEvent::Ready { data_about_bot } => {
             println!("Logged in as {}", data_about_bot.user.name);
-            match data::load() {
-                Ok(restore) => {
-                    *data.tasks.lock().unwrap() = restore.tasks.lock().unwrap().clone();
-                    *data.subjects.lock().unwrap() = restore.subjects.lock().unwrap().clone();
-                    *data.suggest_times.lock().unwrap() =
-                        restore.suggest_times.lock().unwrap().clone();
-                    *data.panel_message.lock().unwrap() = *restore.panel_message.lock().unwrap();
-                    *data.ping_channel.lock().unwrap() = *restore.ping_channel.lock().unwrap();
-                    *data.ping_role.lock().unwrap() = *restore.ping_role.lock().unwrap();
-                    *data.stop_ping_until.lock().unwrap() =
-                        *restore.stop_ping_until.lock().unwrap();
-                    *data.log_channel.lock().unwrap() = *restore.log_channel.lock().unwrap();
-                    *data.warn_users.lock().unwrap() = restore.warn_users.lock().unwrap().clone();
-                    println!("Config restored:");
-                    println!("{:#?}", data);
-                }
-                Err(_) => {
-                    println!("Note: {} not found, using default data", data::FILE_PATH);
-                    data::save(data)?;
-                }
-            }
-            tokio::spawn(periodic::every_day(ctx.clone()));
-            tokio::spawn(periodic::every_minute(ctx.clone()));
-            if let Some(panel_message) = &*data.panel_message.lock().unwrap() {
-                data.panel_listener.lock().unwrap().replace(tokio::spawn(
-                    commands::panel::listen_panel_interactions(ctx.clone(), *panel_message),
-                ));
+            data.metrics
+                .gateway
+                .lock()
+                .unwrap()
+                .replace(ConnectionStage::Connected);
+            println!("Config restored:");
+            println!("{:#?}", data.guilds);
+            for guild_id in data.guild_ids() {
+                commands::panel::restart_listener(ctx, data, guild_id);
             }
         }
         FullEvent::InteractionCreate { interaction } => {
@@ -57,8 +46,13 @@ async fn event_handler(
                     "{}: Invoked by {}",
                     command_interaction.data.name, command_interaction.user.name
                 );
+                data.metrics.command_invoked(&command_interaction.data.name);
             }
         }
+        FullEvent::ShardStageUpdate { event } => {
+            println!("Gateway: {:?} -> {:?}", event.old, event.new);
+            data.metrics.gateway.lock().unwrap().replace(event.new);
+        }
         _ => {}
     }
     Ok(())
@@ -79,28 +73,98 @@ async fn main() {
                 modify_tasks::add_task(),
                 modify_tasks::remove_task(),
                 modify_tasks::edit_task(),
+                recurring_task::add_recurring_task(),
+                recurring_task::remove_recurring_task(),
+                recurring_task::list_recurring_tasks(),
+                trash::undo(),
+                trash::trash(),
+                calendar::export_ics(),
+                calendar::calendar_feed(),
+                calendar::rotate_calendar_feeds(),
+                calendar::import_ics(),
+                calendar::add_import_rule(),
+                calendar::remove_import_rule(),
+                spreadsheet::export_csv(),
+                spreadsheet::import_csv(),
                 modify_subjects::add_subjects(),
                 modify_subjects::remove_subject(),
+                modify_subjects::rename_subject(),
+                modify_subjects::merge_subjects(),
                 modify_suggest_times::add_suggest_time(),
                 modify_suggest_times::remove_suggest_time(),
+                timetable::set_period(),
+                timetable::remove_period(),
+                timetable::show_timetable(),
+                belongings::add_standing_belonging(),
+                belongings::remove_standing_belonging(),
+                belongings::list_standing_belongings(),
+                belongings::skip_belonging(),
+                category_config::add_category(),
+                category_config::edit_category(),
+                category_config::archive_category(),
+                category_config::move_category(),
+                category_config::list_categories(),
                 panel::deploy_panel(),
+                digest_config::add_digest(),
+                digest_config::remove_digest(),
+                digest_config::list_digests(),
                 ping_config::set_ping_channel(),
                 ping_config::set_ping_role(),
                 ping_config::stop_ping(),
                 ping_config::resume_ping(),
                 log_config::set_log_channel(),
+                timezone_config::set_timezone(),
+                restore_backup::restore_backup(),
                 warn_config::enable_warn(),
                 warn_config::disable_warn(),
+                warn_config::warn_settings(),
+                webhook_config::add_webhook(),
+                webhook_config::remove_webhook(),
             ],
             event_handler: |ctx, event, framework, data| {
                 Box::pin(event_handler(ctx, event, framework, data))
             },
             ..Default::default()
         })
-        .setup(|ctx, _ready, framework| {
+        .setup(|ctx, ready, framework| {
             Box::pin(async move {
                 poise::builtins::register_globally(ctx, &framework.options().commands).await?;
-                Ok(Data::default())
+                let legacy_guild = std::env::var("LEGACY_GUILD_ID")
+                    .ok()
+                    .and_then(|id| id.parse().ok())
+                    .or(match ready.guilds.as_slice() {
+                        [guild] => Some(guild.id),
+                        _ => None,
+                    });
+                let storage = storage::open(legacy_guild).expect("Failed to open storage");
+                let data = Arc::new(Data::load(storage)?);
+                tokio::spawn({
+                    let data = data.clone();
+                    let cache = ctx.cache.clone();
+                    async move {
+                        if let Err(e) = http::serve(data, cache).await {
+                            println!("[http] Server stopped: {:?}", e);
+                        }
+                    }
+                });
+                tokio::spawn(webhook::run(data.clone()));
+                // 再接続のたびにReadyが届くので、定期実行はここで1度だけ開始する
+                tokio::spawn(periodic::every_day(data.clone()));
+                tokio::spawn(periodic::every_minute(ctx.clone(), data.clone()));
+                match periodic::backup::destinations() {
+                    Ok(destinations) => {
+                        for (destination, schedule) in destinations {
+                            tokio::spawn(periodic::backup::run(
+                                ctx.clone(),
+                                data.clone(),
+                                destination,
+                                schedule,
+                            ));
+                        }
+                    }
+                    Err(e) => println!("Invalid backup configuration: {:?}", e),
+                }
+                Ok(data)
             })
         })
         .build();
@@ -0,0 +1,182 @@
+//! 監視に使う状態と、Prometheus形式のメトリクスです。
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use poise::serenity_prelude::ConnectionStage;
+
+#[derive(Default)]
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    pub fn inc(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Default)]
+pub struct Metrics {
+    /// ゲートウェイへの接続状態。Readyを受け取るまでは`None`です。
+    pub gateway: Mutex<Option<ConnectionStage>>,
+    pub every_day_succeeded: Mutex<Option<DateTime<Utc>>>,
+    pub every_minute_succeeded: Mutex<Option<DateTime<Utc>>>,
+    /// コマンド名ごとの実行回数
+    pub commands: Mutex<BTreeMap<String, u64>>,
+    pub tasks_created: Counter,
+    pub tasks_deleted: Counter,
+    pub pings_sent: Counter,
+    pub warn_dms_sent: Counter,
+    pub warn_dms_failed: Counter,
+    /// 保存先ごとのバックアップの成功回数
+    pub backups: Mutex<BTreeMap<&'static str, u64>>,
+    /// 保存にかかった時間の合計と回数
+    pub saves: Mutex<(Duration, u64)>,
+}
+
+impl Metrics {
+    pub fn is_connected(&self) -> bool {
+        *self.gateway.lock().unwrap() == Some(ConnectionStage::Connected)
+    }
+
+    pub fn command_invoked(&self, name: &str) {
+        *self
+            .commands
+            .lock()
+            .unwrap()
+            .entry(name.to_string())
+            .or_default() += 1;
+    }
+
+    pub fn backup_succeeded(&self, destination: &'static str) {
+        *self.backups.lock().unwrap().entry(destination).or_default() += 1;
+    }
+
+    pub fn saved(&self, duration: Duration) {
+        let mut saves = self.saves.lock().unwrap();
+        saves.0 += duration;
+        saves.1 += 1;
+    }
+
+    /// Prometheusのテキスト形式で書き出します。
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, samples: Vec<(String, String)>| {
+            writeln!(out, "# HELP task_bot_{} {}", name, help).unwrap();
+            writeln!(out, "# TYPE task_bot_{} {}", name, kind).unwrap();
+            // 名前に続く部分(ラベルや`_sum`など)と値
+            for (suffix, value) in samples {
+                writeln!(out, "task_bot_{}{} {}", name, suffix, value).unwrap();
+            }
+        };
+        let single = |value: u64| vec![(String::new(), value.to_string())];
+        let timestamp = |datetime: Option<DateTime<Utc>>| {
+            datetime.map_or(0.0, |datetime| datetime.timestamp_millis() as f64 / 1000.0)
+        };
+
+        metric(
+            "gateway_connected",
+            "gauge",
+            "Whether the bot is connected to the Discord gateway.",
+            single(self.is_connected().into()),
+        );
+        metric(
+            "last_success_timestamp_seconds",
+            "gauge",
+            "Unix time of the last successful run of each periodic job.",
+            vec![
+                (
+                    "{job=\"every_day\"}".into(),
+                    timestamp(*self.every_day_succeeded.lock().unwrap()).to_string(),
+                ),
+                (
+                    "{job=\"every_minute\"}".into(),
+                    timestamp(*self.every_minute_succeeded.lock().unwrap()).to_string(),
+                ),
+            ],
+        );
+        metric(
+            "commands_total",
+            "counter",
+            "Slash commands invoked.",
+            self.commands
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(name, count)| (format!("{{command=\"{}\"}}", name), count.to_string()))
+                .collect(),
+        );
+        metric(
+            "tasks_created_total",
+            "counter",
+            "Tasks created, including restored ones.",
+            single(self.tasks_created.get()),
+        );
+        metric(
+            "tasks_deleted_total",
+            "counter",
+            "Tasks deleted.",
+            single(self.tasks_deleted.get()),
+        );
+        metric(
+            "pings_sent_total",
+            "counter",
+            "Daily pings sent.",
+            single(self.pings_sent.get()),
+        );
+        metric(
+            "warn_dms_total",
+            "counter",
+            "Deadline warning DMs for tasks in categories with warnings enabled.",
+            vec![
+                (
+                    "{result=\"sent\"}".into(),
+                    self.warn_dms_sent.get().to_string(),
+                ),
+                (
+                    "{result=\"failed\"}".into(),
+                    self.warn_dms_failed.get().to_string(),
+                ),
+            ],
+        );
+        metric(
+            "backups_total",
+            "counter",
+            "Successful backups.",
+            self.backups
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(destination, count)| {
+                    (
+                        format!("{{destination=\"{}\"}}", destination),
+                        count.to_string(),
+                    )
+                })
+                .collect(),
+        );
+        let (sum, count) = *self.saves.lock().unwrap();
+        metric(
+            "save_duration_seconds",
+            "summary",
+            "Time spent saving guild data.",
+            vec![
+                ("_sum".into(), sum.as_secs_f64().to_string()),
+                ("_count".into(), count.to_string()),
+            ],
+        );
+
+        out
+    }
+}
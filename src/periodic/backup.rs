@@ -1,27 +1,241 @@
+use std::{
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
 use anyhow::{Context as _, Error};
-use chrono::Local;
+use chrono::{Duration, NaiveDateTime, Utc};
 use poise::serenity_prelude::*;
-use tokio::fs::File;
+use tokio::time::{Instant, sleep_until};
+
+use crate::{
+    data::Data,
+    periodic::{Schedule, s3::Bucket},
+    storage,
+    utilities::{default_timezone, format_datetime},
+};
+
+const SNAPSHOT_PREFIX: &str = "data-";
+const SNAPSHOT_SUFFIX: &str = ".json";
+const SNAPSHOT_FORMAT: &str = "%Y%m%dT%H%M%S";
+
+/// バックアップの保存先です。
+#[derive(Clone)]
+pub enum Destination {
+    /// ディレクトリにスナップショットを保存し、`keep`個を超えたものと`max_age`を過ぎたものを削除します。
+    Local {
+        dir: PathBuf,
+        keep: usize,
+        max_age: Duration,
+    },
+    /// 各ギルドのログチャンネルに、そのギルドのデータを送ります。
+    Discord,
+    /// S3互換のストレージにスナップショットをアップロードします。
+    S3 { bucket: Bucket, prefix: String },
+}
+
+impl Display for Destination {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Destination::Local { dir, .. } => write!(f, "local directory {}", dir.display()),
+            Destination::Discord => write!(f, "log channels"),
+            Destination::S3 { bucket, .. } => write!(f, "S3 bucket {}", bucket.name),
+        }
+    }
+}
+
+impl Destination {
+    /// メトリクスのラベルに使う名前です。
+    pub fn label(&self) -> &'static str {
+        match self {
+            Destination::Local { .. } => "local",
+            Destination::Discord => "discord",
+            Destination::S3 { .. } => "s3",
+        }
+    }
+}
+
+fn env_or<T: std::str::FromStr>(key: &str, default: T) -> Result<T, Error>
+where
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    match std::env::var(key) {
+        Ok(value) => value.parse().with_context(|| format!("Invalid {}", key)),
+        Err(_) => Ok(default),
+    }
+}
+
+/// `BACKUP_{name}_SCHEDULE`を読み込みます。`off`の場合は`None`を返します。
+fn schedule(name: &str, default: &str) -> Result<Option<Schedule>, Error> {
+    let key = format!("BACKUP_{}_SCHEDULE", name);
+    match std::env::var(&key).unwrap_or(default.into()).as_str() {
+        "off" => Ok(None),
+        schedule => Ok(Some(
+            schedule
+                .parse()
+                .with_context(|| format!("Invalid {}", key))?,
+        )),
+    }
+}
+
+/// 環境変数から、有効なバックアップの保存先とそのスケジュールを読み込みます。
+///
+/// S3は`BACKUP_S3_BUCKET`が設定されている場合のみ有効になります。
+pub fn destinations() -> Result<Vec<(Destination, Schedule)>, Error> {
+    let mut destinations = vec![];
+
+    if let Some(schedule) = schedule("LOCAL", "6h")? {
+        let keep = env_or("BACKUP_LOCAL_KEEP", 28)?;
+        // 0個の場合、書き出したばかりのスナップショットも削除してしまう
+        anyhow::ensure!(keep > 0, "BACKUP_LOCAL_KEEP must be at least 1");
+        // 同じく、0日以下の場合は書き出したばかりのスナップショットも期限切れになる
+        let max_age_days = env_or("BACKUP_LOCAL_MAX_AGE_DAYS", 14)?;
+        anyhow::ensure!(
+            max_age_days > 0,
+            "BACKUP_LOCAL_MAX_AGE_DAYS must be at least 1"
+        );
+        destinations.push((
+            Destination::Local {
+                dir: env_or("BACKUP_LOCAL_DIR", PathBuf::from("backups"))?,
+                keep,
+                max_age: Duration::try_days(max_age_days)
+                    .context("BACKUP_LOCAL_MAX_AGE_DAYS is too large")?,
+            },
+            schedule,
+        ));
+    }
+
+    if let Some(schedule) = schedule("DISCORD", "12:00")? {
+        destinations.push((Destination::Discord, schedule));
+    }
+
+    if let Ok(name) = std::env::var("BACKUP_S3_BUCKET")
+        && let Some(schedule) = schedule("S3", "12:00")?
+    {
+        let var = |key: &str| std::env::var(key).with_context(|| format!("Missing {}", key));
+        destinations.push((
+            Destination::S3 {
+                bucket: Bucket {
+                    endpoint: var("BACKUP_S3_ENDPOINT")?,
+                    region: env_or("BACKUP_S3_REGION", "us-east-1".to_string())?,
+                    name,
+                    access_key: var("BACKUP_S3_ACCESS_KEY")?,
+                    secret_key: var("BACKUP_S3_SECRET_KEY")?,
+                },
+                prefix: env_or("BACKUP_S3_PREFIX", String::new())?,
+            },
+            schedule,
+        ));
+    }
+
+    Ok(destinations)
+}
+
+/// `schedule`に従って、`destination`へのバックアップを繰り返します。
+pub async fn run(ctx: Context, data: Arc<Data>, destination: Destination, schedule: Schedule) {
+    loop {
+        let now = Utc::now();
+        let target_time = schedule.next(now, default_timezone());
+        println!("[backup] Next backup to {} at {}", destination, target_time);
+
+        sleep_until(Instant::now() + (target_time - now).to_std().unwrap()).await;
+        match backup(&ctx, &data, &destination).await {
+            Ok(()) => data.metrics.backup_succeeded(destination.label()),
+            Err(e) => println!("[backup] Failed to backup to {}: {:?}", destination, e),
+        }
+    }
+}
+
+pub async fn backup(ctx: &Context, data: &Data, destination: &Destination) -> Result<(), Error> {
+    match destination {
+        Destination::Local { dir, keep, max_age } => {
+            fs::create_dir_all(dir)?;
+            let snapshot = storage::export(&*data.storage, &data.guild_ids())?;
+            let path = dir.join(snapshot_name());
+            storage::write_atomic(&path, snapshot.as_bytes())?;
+            println!("[backup] Saved {}", path.display());
+            rotate(dir, *keep, *max_age)
+        }
+        Destination::Discord => {
+            for guild_id in data.guild_ids() {
+                if let Err(e) = send_to_log_channel(ctx, data, guild_id).await {
+                    println!("{}: Failed to backup: {:?}", guild_id, e);
+                }
+            }
+            Ok(())
+        }
+        Destination::S3 { bucket, prefix } => {
+            let snapshot = storage::export(&*data.storage, &data.guild_ids())?;
+            let key = format!("{}{}", prefix, snapshot_name());
+            bucket.put(&key, snapshot.into_bytes()).await?;
+            println!("[backup] Uploaded {} to bucket {}", key, bucket.name);
+            Ok(())
+        }
+    }
+}
+
+/// スナップショットの名前や保存期間に使う、`DEFAULT_TIMEZONE`での現在の日時です。
+fn now_local() -> NaiveDateTime {
+    Utc::now().with_timezone(&default_timezone()).naive_local()
+}
+
+fn snapshot_name() -> String {
+    format!(
+        "{}{}{}",
+        SNAPSHOT_PREFIX,
+        now_local().format(SNAPSHOT_FORMAT),
+        SNAPSHOT_SUFFIX
+    )
+}
+
+/// 新しい順に`keep`個まで、かつ`max_age`以内のスナップショットを残し、それ以外を削除します。
+fn rotate(dir: &Path, keep: usize, max_age: Duration) -> Result<(), Error> {
+    let mut snapshots = fs::read_dir(dir)?
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let name = entry.file_name().into_string().ok()?;
+            let timestamp = name
+                .strip_prefix(SNAPSHOT_PREFIX)?
+                .strip_suffix(SNAPSHOT_SUFFIX)?;
+            let datetime = NaiveDateTime::parse_from_str(timestamp, SNAPSHOT_FORMAT).ok()?;
+            Some((datetime, entry.path()))
+        })
+        .collect::<Vec<_>>();
+    snapshots.sort_by_key(|(datetime, _)| std::cmp::Reverse(*datetime));
+
+    let threshold = now_local() - max_age;
+    for (i, (datetime, path)) in snapshots.into_iter().enumerate() {
+        if keep <= i || datetime < threshold {
+            fs::remove_file(&path)?;
+            println!("[backup] Removed {}", path.display());
+        }
+    }
+
+    Ok(())
+}
 
-use crate::{data, utilities::format_datetime};
+async fn send_to_log_channel(ctx: &Context, data: &Data, guild_id: GuildId) -> Result<(), Error> {
+    let guild = data.guild(guild_id);
+    let Some(log_channel) = *guild.log_channel.lock().unwrap() else {
+        println!("{}: Log channel not set; Skipping backup", guild_id);
+        return Ok(());
+    };
 
-pub async fn backup(ctx: &Context) -> Result<(), Error> {
-    let data = data::load()?;
-    let log_channel = (*data.log_channel.lock().unwrap()).context("Log channel not set")?;
+    // ギルドごとに、そのギルドのデータのみをバックアップする
+    let backup = storage::export(&*data.storage, &[guild_id])?;
 
     log_channel
         .send_files(
             ctx,
-            vec![
-                CreateAttachment::file(
-                    &File::open(data::FILE_PATH).await?,
-                    format!("{}.json", Local::now().timestamp()),
-                )
-                .await?,
-            ],
+            vec![CreateAttachment::bytes(
+                backup,
+                format!("{}.json", Utc::now().timestamp()),
+            )],
             CreateMessage::default().embed(CreateEmbed::default().title(format!(
                 "データのバックアップ ({})",
-                format_datetime(Local::now())
+                format_datetime(Utc::now(), guild.timezone())
             ))),
         )
         .await?;
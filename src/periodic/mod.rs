@@ -1,6 +1,11 @@
 mod wait;
 pub use wait::every_day;
 pub use wait::every_minute;
+pub use wait::grace_period;
+mod schedule;
+pub use schedule::Schedule;
 pub mod backup;
 pub mod ping;
+mod s3;
+pub mod trash;
 pub mod warn;
@@ -1,121 +1,262 @@
 use anyhow::{Context as _, Error};
-use chrono::{DateTime, Duration, Local, NaiveTime, TimeDelta};
+use chrono::{DateTime, TimeDelta, Utc};
 use itertools::Itertools;
 use poise::serenity_prelude::*;
 
-use crate::{data, PoiseContext, Task};
+use crate::{
+    PoiseContext, Subject, Task,
+    data::{self, Data, Digest, GuildData},
+    webhook::{EventKind, Payload},
+};
 
-fn search_tasks(from: DateTime<Local>, to: DateTime<Local>) -> Result<Vec<Task>, Error> {
-    let data = data::load()?;
+/// 埋め込みに表示できるフィールドの最大数です。
+const MAX_FIELDS: usize = 25;
+/// フィールドの値の最大の文字数です。
+const MAX_FIELD_VALUE: usize = 1024;
 
-    let tasks = data.tasks.lock().unwrap().clone();
-
-    Ok(tasks
-        .iter()
-        .filter(|task| from <= task.datetime && task.datetime < to)
-        .sorted_by_key(|task| task.datetime)
-        .cloned()
-        .collect())
+/// 持ち物の欄の内容です。入りきらない分は「他N件」とします。
+fn format_belongings(belongings: &[String]) -> String {
+    // 「他N件」の分を空けておく
+    let limit = MAX_FIELD_VALUE - 16;
+    let mut lines = vec![];
+    let mut len = 0;
+    for item in belongings {
+        let line = format!("- {}", item);
+        let line_len = line.chars().count() + usize::from(!lines.is_empty());
+        if limit < len + line_len {
+            break;
+        }
+        len += line_len;
+        lines.push(line);
+    }
+    if lines.len() < belongings.len() {
+        lines.push(format!("(他{}件)", belongings.len() - lines.len()));
+    }
+    lines.join("\n")
 }
 
-fn embed(tasks: Vec<Task>) -> CreateEmbed {
-    let fields = tasks.iter().map(|task| task.to_field()).collect::<Vec<_>>();
+fn embed(
+    guild: &GuildData,
+    digest: &Digest,
+    mut tasks: Vec<Task>,
+    belongings: Vec<String>,
+) -> CreateEmbed {
+    tasks.sort_by_key(guild.task_order());
+    // 持ち物の欄の分を空けておく
+    let max_fields = MAX_FIELDS - usize::from(!belongings.is_empty());
+    let fields = tasks
+        .iter()
+        .take(max_fields)
+        .map(|task| task.to_field(guild))
+        .collect::<Vec<_>>();
 
-    if !fields.is_empty() {
+    let embed = if !fields.is_empty() {
+        let mut description = format!("{}のタスクをお知らせします！", digest.window);
+        if max_fields < tasks.len() {
+            description += &format!("\n(他{}件)", tasks.len() - max_fields);
+        }
         CreateEmbed::default()
-            .title("タスク通知")
-            .description("明日のタスクをお知らせします！")
+            .title(&digest.title)
+            .description(description)
             .fields(fields)
-            .color(Color::RED)
+            .color(guild.list_color(&tasks, Color::RED))
     } else {
         CreateEmbed::default()
-            .title("タスク通知")
-            .description("明日のタスクはありません:tada:")
+            .title(&digest.title)
+            .description(format!("{}のタスクはありません:tada:", digest.window))
             .color(Color::DARK_GREEN)
+    };
+
+    if belongings.is_empty() {
+        embed
+    } else {
+        embed.field("明日の持ち物", format_belongings(&belongings), false)
     }
 }
 
-fn tomorrow(now: DateTime<Local>) -> (DateTime<Local>, DateTime<Local>) {
-    let from = (now + Duration::days(1))
-        .with_time(NaiveTime::from_hms_opt(0, 0, 0).unwrap())
-        .unwrap();
-    let to = (now + Duration::days(2))
-        .with_time(NaiveTime::from_hms_opt(0, 0, 0).unwrap())
-        .unwrap();
+/// `from`の日(明日)の持ち物です。常備の持ち物と、その日の持ち物の種類のタスクをまとめます。
+///
+/// まとめた持ち物のタスクは`tasks`から取り除きます。
+fn take_belongings(guild: &GuildData, tasks: &mut Vec<Task>, from: DateTime<Utc>) -> Vec<String> {
+    let tz = guild.timezone();
+    let tomorrow = from.with_timezone(&tz).date_naive();
 
-    (from, to)
+    let mut belongings = guild.belongings_on(tomorrow);
+    let (explicit, rest) = tasks.drain(..).partition::<Vec<_>, _>(|task| {
+        guild.category(&task.category).belongings
+            && task.datetime.with_timezone(&tz).date_naive() == tomorrow
+    });
+    *tasks = rest;
+    belongings.extend(explicit.into_iter().map(|task| match task.subject {
+        Subject::Set(subject) => format!("{} ({})", task.details, subject),
+        Subject::Unset => task.details,
+    }));
+    belongings
 }
 
-pub async fn ping(ctx: &Context) -> Result<(), Error> {
-    let data = data::load()?;
+/// `now`までに送る時刻を迎え、まだ送っていないダイジェストと、その予定日時です。
+///
+/// 予定日時から`grace`より後になったものは送りません。
+/// 初めて見るダイジェストは、それまでの分を送ったものとして扱います。
+pub fn due(
+    data: &Data,
+    now: DateTime<Utc>,
+    grace: TimeDelta,
+) -> Result<Vec<(GuildId, Digest, DateTime<Utc>)>, Error> {
+    let mut due = vec![];
 
-    let stop_ping_until = *data.stop_ping_until.lock().unwrap();
-    if Local::now() < stop_ping_until {
-        println!("Ping stopped until {}", stop_ping_until);
-        return Ok(());
+    for guild_id in data.guild_ids() {
+        let guild = data.guild(guild_id);
+        let tz = guild.timezone();
+        let digests = guild.digests();
+
+        let mut changed = false;
+        {
+            let mut sent_digests = guild.sent_digests.lock().unwrap();
+            let len = sent_digests.len();
+            sent_digests.retain(|title, _| digests.iter().any(|digest| digest.title == *title));
+            changed |= sent_digests.len() < len;
+
+            for digest in digests {
+                let scheduled = digest.last(now, tz);
+                match sent_digests.get(&digest.title) {
+                    None => {
+                        sent_digests.insert(digest.title.clone(), now);
+                        changed = true;
+                    }
+                    Some(sent) if *sent < scheduled && now - scheduled <= grace => {
+                        due.push((guild_id, digest, scheduled));
+                    }
+                    Some(_) => {}
+                }
+            }
+        }
+        if changed {
+            data::save(data, guild_id)?;
+        }
     }
 
-    let ping_channel = (*data.ping_channel.lock().unwrap()).context("Ping channel not set")?;
-    let ping_role = (*data.ping_role.lock().unwrap()).context("Ping role not set")?;
+    Ok(due)
+}
+
+/// ダイジェストを送ります。
+pub async fn ping(
+    ctx: &Context,
+    data: &Data,
+    digests: &[(GuildId, Digest, DateTime<Utc>)],
+) -> Result<(), Error> {
+    for (guild_id, digest, scheduled) in digests {
+        if let Err(e) = ping_guild(ctx, data, *guild_id, digest, *scheduled).await {
+            println!("{}: Failed to ping {}: {:?}", guild_id, digest.title, e);
+        }
+    }
+
+    Ok(())
+}
 
-    let (from, to) = tomorrow(Local::now());
+async fn ping_guild(
+    ctx: &Context,
+    data: &Data,
+    guild_id: GuildId,
+    digest: &Digest,
+    scheduled: DateTime<Utc>,
+) -> Result<(), Error> {
+    let guild = data.guild(guild_id);
+    let mark_sent = || {
+        guild
+            .sent_digests
+            .lock()
+            .unwrap()
+            .insert(digest.title.clone(), scheduled);
+        data::save(data, guild_id)
+    };
+
+    let stop_ping_until = *guild.stop_ping_until.lock().unwrap();
+    if Utc::now() < stop_ping_until {
+        println!("Ping stopped until {}", stop_ping_until);
+        return mark_sent();
+    }
+
+    // 遅れて送る場合も、予定日時に送った場合と同じ範囲にする
+    let (from, to) = digest.range(scheduled, guild.timezone());
 
     println!("Searching tasks: from {} to {}", from, to);
 
-    let tasks = search_tasks(from, to)?;
-    ping_channel
+    let mut tasks = data.storage.tasks_between(guild_id, from, to)?;
+    let payload = Payload {
+        tasks: tasks.clone(),
+        ..Payload::new(EventKind::PingSent, guild_id)
+    };
+    let mention = match digest.role {
+        Some(role) if !tasks.is_empty() => format!("{}", role.mention()),
+        _ => "".into(),
+    };
+    let belongings = take_belongings(&guild, &mut tasks, from);
+    digest
+        .channel
         .send_message(
             ctx,
             CreateMessage::default()
-                .content(if !tasks.is_empty() {
-                    format!("{}", ping_role.mention())
-                } else {
-                    "".into()
-                })
-                .embed(embed(tasks)),
+                .content(mention)
+                .embed(embed(&guild, digest, tasks, belongings)),
         )
         .await?;
+    mark_sent()?;
+    data.metrics.pings_sent.inc();
+    data.notify(&payload);
 
     Ok(())
 }
 
+/// 最近送ったダイジェストを、現在のタスクに合わせて更新します。
 pub async fn update(ctx: &PoiseContext<'_>) -> Result<Vec<Message>, Error> {
-    let data = data::load()?;
+    let guild_id = ctx.guild_id().context("Not in a guild")?;
+    let guild = ctx.data().guild(guild_id);
+    let tz = guild.timezone();
 
-    let ping_channel = (*data.ping_channel.lock().unwrap()).context("Ping channel not set")?;
     let mut updated_messages = vec![];
 
-    let prev_messages = ping_channel
-        .messages(ctx, GetMessages::default())
-        .await?
-        .into_iter()
-        .sorted_by_key(|m| m.id.created_at())
-        .rev()
-        .filter(|m| {
-            m.author.id == ctx.framework().bot_id
-                && Local::now().date_naive() - TimeDelta::days(1) <= m.id.created_at().date_naive()
-                && m.referenced_message.is_none()
-                && m.interaction_metadata.is_none()
-                && m.embeds[0].title.clone().unwrap() == "タスク通知"
-        });
-
-    for mut prev_message in prev_messages {
-        let (from, to) = tomorrow(prev_message.id.created_at().with_timezone(&Local));
-
-        let prev_embed = prev_message.embeds[0].clone();
-        let new_embed = embed(search_tasks(from, to)?);
-
-        if CreateEmbed::from(prev_embed) != new_embed {
-            prev_message
-                .edit(ctx, EditMessage::default().embed(new_embed))
-                .await?;
-            println!("{}: Message updated", prev_message.id.created_at());
-            updated_messages.push(prev_message);
-        } else {
-            println!(
-                "{}: No changes; Updating not needed",
-                prev_message.id.created_at()
-            );
+    for digest in guild.digests() {
+        let prev_messages = digest
+            .channel
+            .messages(ctx, GetMessages::default())
+            .await?
+            .into_iter()
+            .sorted_by_key(|m| m.id.created_at())
+            .rev()
+            .filter(|m| {
+                m.author.id == ctx.framework().bot_id
+                    && Utc::now().with_timezone(&tz).date_naive() - TimeDelta::days(1)
+                        <= m.id.created_at().with_timezone(&tz).date_naive()
+                    && m.referenced_message.is_none()
+                    && m.interaction_metadata.is_none()
+                    && m.embeds
+                        .first()
+                        .is_some_and(|embed| embed.title.as_ref() == Some(&digest.title))
+            });
+
+        for mut prev_message in prev_messages {
+            // 遅れて送ったダイジェストも、送る予定だった日時の範囲で更新する
+            let scheduled = digest.last(prev_message.id.created_at().with_timezone(&Utc), tz);
+            let (from, to) = digest.range(scheduled, tz);
+
+            let prev_embed = prev_message.embeds[0].clone();
+            let mut tasks = ctx.data().storage.tasks_between(guild_id, from, to)?;
+            let belongings = take_belongings(&guild, &mut tasks, from);
+            let new_embed = embed(&guild, &digest, tasks, belongings);
+
+            if CreateEmbed::from(prev_embed) != new_embed {
+                prev_message
+                    .edit(ctx, EditMessage::default().embed(new_embed))
+                    .await?;
+                println!("{}: Message updated", prev_message.id.created_at());
+                updated_messages.push(prev_message);
+            } else {
+                println!(
+                    "{}: No changes; Updating not needed",
+                    prev_message.id.created_at()
+                );
+            }
         }
     }
 
@@ -0,0 +1,106 @@
+use anyhow::{Context as _, Error};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+const SIGNED_HEADERS: &str = "host;x-amz-content-sha256;x-amz-date";
+
+/// S3互換のストレージ(MinIOなど)のバケットです。
+///
+/// パス形式のURL(`{endpoint}/{bucket}/{key}`)でアクセスし、リクエストはAWS Signature Version 4で署名します。
+#[derive(Clone)]
+pub struct Bucket {
+    pub endpoint: String,
+    pub region: String,
+    pub name: String,
+    pub access_key: String,
+    pub secret_key: String,
+}
+
+impl Bucket {
+    pub async fn put(&self, key: &str, body: Vec<u8>) -> Result<(), Error> {
+        let url = reqwest::Url::parse(&format!(
+            "{}/{}/{}",
+            self.endpoint.trim_end_matches('/'),
+            encode(&self.name),
+            key.split('/').map(encode).collect::<Vec<_>>().join("/")
+        ))?;
+        let host = url.host_str().context("Invalid S3 endpoint")?;
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", host, port),
+            None => host.to_string(),
+        };
+
+        let now = Utc::now();
+        let datetime = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let payload_hash = hex::encode(Sha256::digest(&body));
+
+        let canonical_request = format!(
+            "PUT\n{}\n\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+            url.path(),
+            host,
+            payload_hash,
+            datetime,
+            SIGNED_HEADERS,
+            payload_hash
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            datetime,
+            scope,
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+        let signing_key = [date.as_str(), &self.region, "s3", "aws4_request"]
+            .into_iter()
+            .fold(
+                format!("AWS4{}", self.secret_key).into_bytes(),
+                |key, data| hmac(&key, data),
+            );
+        let signature = hex::encode(hmac(&signing_key, &string_to_sign));
+
+        let response = reqwest::Client::new()
+            .put(url)
+            .header("x-amz-date", datetime)
+            .header("x-amz-content-sha256", payload_hash)
+            .header(
+                "authorization",
+                format!(
+                    "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                    self.access_key, scope, SIGNED_HEADERS, signature
+                ),
+            )
+            .body(body)
+            .send()
+            .await?;
+
+        let status = response.status();
+        anyhow::ensure!(
+            status.is_success(),
+            "S3 upload failed: {} {}",
+            status,
+            response.text().await.unwrap_or_default()
+        );
+        Ok(())
+    }
+}
+
+fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// パスの1要素をURIエンコードします。
+fn encode(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
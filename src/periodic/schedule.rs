@@ -0,0 +1,58 @@
+use std::str::FromStr;
+
+use anyhow::{Context as _, Error};
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, Utc};
+use chrono_tz::Tz;
+
+use crate::utilities::to_utc;
+
+/// 定期実行のスケジュールです。
+///
+/// `12:00`のような時刻は毎日その時刻に、`30m`・`6h`・`1d`のような間隔はその間隔ごとに実行します。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Schedule {
+    Daily(NaiveTime),
+    Every(Duration),
+}
+
+impl Schedule {
+    /// `now`より後で、次に実行する日時を返します。時刻は`tz`での時刻として扱います。
+    pub fn next(&self, now: DateTime<Utc>, tz: Tz) -> DateTime<Utc> {
+        match self {
+            Schedule::Daily(time) => {
+                let today = now.with_timezone(&tz).date_naive();
+                let at = |date: NaiveDate| to_utc(tz, date.and_time(*time));
+                if at(today) <= now {
+                    at(today + Duration::days(1))
+                } else {
+                    at(today)
+                }
+            }
+            Schedule::Every(interval) => now + *interval,
+        }
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(time) = NaiveTime::parse_from_str(s, "%H:%M") {
+            return Ok(Schedule::Daily(time));
+        }
+
+        let unit = s.chars().last().context("Empty schedule")?;
+        let amount: i64 = s[..s.len() - unit.len_utf8()]
+            .parse()
+            .with_context(|| format!("Invalid schedule: {}", s))?;
+        let interval = match unit {
+            'm' => Duration::try_minutes(amount),
+            'h' => Duration::try_hours(amount),
+            'd' => Duration::try_days(amount),
+            _ => anyhow::bail!("Invalid schedule: {}", s),
+        }
+        .with_context(|| format!("Invalid schedule: {}", s))?;
+        anyhow::ensure!(interval > Duration::zero(), "Invalid schedule: {}", s);
+        Ok(Schedule::Every(interval))
+    }
+}
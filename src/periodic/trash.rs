@@ -0,0 +1,40 @@
+use anyhow::{Context as _, Error};
+use chrono::{Duration, Utc};
+
+use crate::data::{self, Data};
+
+/// ゴミ箱の保持期間の既定値(日)です。環境変数`TRASH_RETENTION_DAYS`で変更できます。
+const DEFAULT_RETENTION_DAYS: i64 = 30;
+
+/// ゴミ箱の保持期間です。
+///
+/// 起動時に1度だけ読み込み、`Data::trash_retention`に保持します。
+pub fn retention() -> Result<Duration, Error> {
+    let days = match std::env::var("TRASH_RETENTION_DAYS") {
+        Ok(days) => days.parse().context("Invalid TRASH_RETENTION_DAYS")?,
+        Err(_) => DEFAULT_RETENTION_DAYS,
+    };
+    anyhow::ensure!(1 <= days, "TRASH_RETENTION_DAYS must be at least 1");
+    Duration::try_days(days).context("TRASH_RETENTION_DAYS is too large")
+}
+
+/// 保持期間を過ぎた変更履歴を削除します。
+pub fn purge(data: &Data) -> Result<(), Error> {
+    let threshold = Utc::now() - data.trash_retention;
+
+    for guild_id in data.guild_ids() {
+        let purged = {
+            let guild = data.guild(guild_id);
+            let mut history = guild.history.lock().unwrap();
+            let len = history.len();
+            history.retain(|change| threshold <= change.datetime);
+            len - history.len()
+        };
+        if purged > 0 {
+            println!("{}: Purged {} trash entries", guild_id, purged);
+            data::save(data, guild_id)?;
+        }
+    }
+
+    Ok(())
+}
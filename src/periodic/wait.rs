@@ -1,36 +1,92 @@
-use chrono::{Duration, Local, NaiveTime, Timelike};
+use std::sync::Arc;
+
+use anyhow::{Context as _, Error};
+use chrono::{Duration, NaiveTime, Timelike, Utc};
 use poise::serenity_prelude::*;
 use tokio::time::{Instant, sleep_until};
 
-use crate::periodic::{backup, ping, warn};
+use crate::{
+    data::Data,
+    periodic::{ping, trash, warn},
+    recurrence,
+};
+
+/// 通知の猶予の既定値(分)です。環境変数`CATCH_UP_GRACE_MINUTES`で変更できます。
+const DEFAULT_GRACE_MINUTES: i64 = 30;
+
+/// 再起動などで予定の時刻に送れなかった通知は、予定からこの時間が過ぎるまで送ります。
+///
+/// 起動時に1度だけ読み込み、`Data::grace_period`に保持します。
+pub fn grace_period() -> Result<Duration, Error> {
+    let minutes = match std::env::var("CATCH_UP_GRACE_MINUTES") {
+        Ok(minutes) => minutes.parse().context("Invalid CATCH_UP_GRACE_MINUTES")?,
+        Err(_) => DEFAULT_GRACE_MINUTES,
+    };
+    anyhow::ensure!(0 <= minutes, "CATCH_UP_GRACE_MINUTES must not be negative");
+    Duration::try_minutes(minutes).context("CATCH_UP_GRACE_MINUTES is too large")
+}
+
+/// 毎日UTCの0時に、ゴミ箱を整理し、繰り返しのタスクの先の回を作成します。
+///
+/// 繰り返しのタスクは、起動直後にも作成します。
+pub async fn every_day(data: Arc<Data>) {
+    if let Err(e) = recurrence::materialize_all(&data) {
+        println!("[every_day] Failed to create recurring tasks: {:?}", e);
+    }
 
-pub async fn every_day(ctx: Context) {
     loop {
-        let now = Local::now();
-        let target_time = {
-            let time = Local::now()
-                .with_time(NaiveTime::from_hms_opt(12, 0, 0).unwrap())
-                .unwrap();
-            if time <= now {
-                time + Duration::days(1)
-            } else {
-                time
-            }
-        };
+        let now = Utc::now();
+        let target_time = (now + Duration::days(1))
+            .date_naive()
+            .and_time(NaiveTime::MIN)
+            .and_utc();
         println!("[every_day] Next execution at {}", target_time);
-        let sleep_duration = target_time - now;
 
-        sleep_until(Instant::now() + sleep_duration.to_std().unwrap()).await;
-        ping::ping(&ctx).await.expect("Failed to ping");
-        backup::backup(&ctx).await.expect("Failed to backup");
+        sleep_until(Instant::now() + (target_time - now).to_std().unwrap()).await;
+        let mut succeeded = true;
+        if let Err(e) = trash::purge(&data) {
+            println!("[every_day] Failed to purge trash: {:?}", e);
+            succeeded = false;
+        }
+        if let Err(e) = recurrence::materialize_all(&data) {
+            println!("[every_day] Failed to create recurring tasks: {:?}", e);
+            succeeded = false;
+        }
+        if succeeded {
+            data.metrics
+                .every_day_succeeded
+                .lock()
+                .unwrap()
+                .replace(Utc::now());
+        }
     }
 }
 
-pub async fn every_minute(ctx: Context) {
+/// 毎分、送る時刻を迎えた期限接近通知とダイジェストを送ります。
+///
+/// 起動直後にも実行し、停止中に送れなかったものを送ります。
+pub async fn every_minute(ctx: Context, data: Arc<Data>) {
     loop {
-        let now = Local::now();
+        let mut succeeded = true;
+        if let Err(e) = warn::warn(&ctx, &data).await {
+            println!("[every_minute] Failed to warn: {:?}", e);
+            succeeded = false;
+        }
+        if let Err(e) = send_digests(&ctx, &data).await {
+            println!("[every_minute] Failed to ping: {:?}", e);
+            succeeded = false;
+        }
+        if succeeded {
+            data.metrics
+                .every_minute_succeeded
+                .lock()
+                .unwrap()
+                .replace(Utc::now());
+        }
+
+        let now = Utc::now();
         let target_time = {
-            let time = Local::now()
+            let time = Utc::now()
                 .with_second(0)
                 .and_then(|t| t.with_nanosecond(0))
                 .unwrap();
@@ -44,6 +100,10 @@ pub async fn every_minute(ctx: Context) {
         let sleep_duration = target_time - now;
 
         sleep_until(Instant::now() + sleep_duration.to_std().unwrap()).await;
-        warn::warn(&ctx).await.expect("Failed to warn");
     }
 }
+
+async fn send_digests(ctx: &Context, data: &Data) -> Result<(), Error> {
+    let digests = ping::due(data, Utc::now(), data.grace_period)?;
+    ping::ping(ctx, data, &digests).await
+}
@@ -1,64 +1,112 @@
 use anyhow::Error;
-use chrono::{DateTime, Duration, Local, Timelike};
-use itertools::Itertools;
+use chrono::{DateTime, Duration, Utc};
 use poise::serenity_prelude::*;
 
-use crate::{Category, Task, data};
+use crate::{
+    Task,
+    data::{self, Data, GuildData, SentWarning},
+    utilities::format_minutes,
+};
 
-fn search_tasks(from: DateTime<Local>, to: DateTime<Local>) -> Result<Vec<Task>, Error> {
-    let data = data::load()?;
-
-    let tasks = data.tasks.lock().unwrap().clone();
-
-    Ok(tasks
-        .iter()
-        .filter(|task| task.category == Category::Homework)
-        .filter(|task| from <= task.datetime && task.datetime < to)
-        .sorted_by_key(|task| task.datetime)
-        .cloned()
-        .collect())
+fn embed(guild: &GuildData, task: &Task, offset: u32) -> CreateEmbed {
+    let category = guild.category(&task.category);
+    CreateEmbed::default()
+        .title(format!("{}の期限が接近しています", category.name))
+        .description(format!("期限の{}前です", format_minutes(offset)))
+        .fields([task.to_field(guild)])
+        .color(category.color.unwrap_or(Color::RED))
 }
 
-fn period(now: DateTime<Local>) -> (DateTime<Local>, DateTime<Local>) {
-    let from = (now + Duration::hours(1))
-        .with_second(0)
-        .and_then(|t| t.with_nanosecond(0))
-        .unwrap();
-    let to = (now + Duration::hours(1) + Duration::minutes(1))
-        .with_second(0)
-        .and_then(|t| t.with_nanosecond(0))
-        .unwrap();
+/// 各ユーザーが購読しているタスク1件ごとに、設定したタイミングでDMを送ります。同じ通知は1度だけ送ります。
+pub async fn warn(ctx: &Context, data: &Data) -> Result<(), Error> {
+    let now = Utc::now();
+    for guild_id in data.guild_ids() {
+        if let Err(e) = warn_guild(ctx, data, guild_id, now).await {
+            println!("{}: Failed to warn: {:?}", guild_id, e);
+        }
+    }
 
-    (from, to)
+    Ok(())
 }
 
-fn embed(tasks: &Vec<Task>) -> CreateEmbed {
-    let fields = tasks.iter().map(|task| task.to_field()).collect::<Vec<_>>();
-
-    CreateEmbed::default()
-        .title("宿題の期限が接近しています")
-        .fields(fields)
-        .color(Color::RED)
-}
+async fn warn_guild(
+    ctx: &Context,
+    data: &Data,
+    guild_id: GuildId,
+    now: DateTime<Utc>,
+) -> Result<(), Error> {
+    // 猶予より前のものは、タイミングを追加した直後などにまとめて送られないよう送らない
+    let grace = data.grace_period;
+    let guild = data.guild(guild_id);
+    let subscriptions = guild.subscriptions.lock().unwrap().clone();
+    let Some(max_offset) = subscriptions
+        .values()
+        .flat_map(|subscription| &subscription.offsets)
+        .max()
+    else {
+        return Ok(());
+    };
 
-pub async fn warn(ctx: &Context) -> Result<(), Error> {
-    let data = data::load()?;
+    // 期限を過ぎたタスクの記録は不要になる
+    let mut changed = {
+        let mut sent_warnings = guild.sent_warnings.lock().unwrap();
+        let len = sent_warnings.len();
+        sent_warnings.retain(|sent| now < sent.deadline);
+        sent_warnings.len() < len
+    };
 
-    let (from, to) = period(Local::now());
+    let tasks = data
+        .storage
+        .tasks_between(
+            guild_id,
+            now,
+            now + Duration::minutes(*max_offset as i64 + 1),
+        )?
+        .into_iter()
+        .filter(|task| guild.category(&task.category).warn)
+        .collect::<Vec<_>>();
 
-    println!("Searching for homework tasks from {} to {}", from, to);
+    for (&user, subscription) in &subscriptions {
+        for &offset in &subscription.offsets {
+            for task in tasks.iter().filter(|task| subscription.matches(task)) {
+                let warn_at = task.datetime - Duration::minutes(offset as i64);
+                let sent = SentWarning {
+                    user,
+                    task: task.id,
+                    deadline: task.datetime,
+                    offset,
+                };
+                if now < warn_at || warn_at + grace < now {
+                    continue;
+                }
+                // 送信中に重ねて呼ばれても二重に送らないよう、送る前に記録しておく
+                if !guild.sent_warnings.lock().unwrap().insert(sent.clone()) {
+                    continue;
+                }
 
-    let tasks = search_tasks(from, to)?;
-    if tasks.is_empty() {
-        println!("No homework tasks to warn about.");
-        return Ok(());
+                match user
+                    .direct_message(
+                        ctx,
+                        CreateMessage::default().embed(embed(&guild, task, offset)),
+                    )
+                    .await
+                {
+                    Ok(_) => {
+                        data.metrics.warn_dms_sent.inc();
+                        changed = true;
+                    }
+                    Err(e) => {
+                        println!("{}: Failed to send DM to {}: {:?}", guild_id, user, e);
+                        data.metrics.warn_dms_failed.inc();
+                        guild.sent_warnings.lock().unwrap().remove(&sent);
+                    }
+                }
+            }
+        }
     }
 
-    let warn_users = data.warn_users.lock().unwrap().clone();
-
-    for user in warn_users {
-        user.direct_message(ctx, CreateMessage::default().embed(embed(&tasks)))
-            .await?;
+    if changed {
+        data::save(data, guild_id)?;
     }
 
     Ok(())
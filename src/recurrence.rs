@@ -0,0 +1,372 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::{Context as _, Error};
+use chrono::{Datelike, Duration, NaiveDate, Utc};
+use itertools::Itertools;
+use poise::serenity_prelude::*;
+use uuid::Uuid;
+
+use crate::{
+    Task,
+    data::{self, Change, Data, Recurrence, RecurringTask, SeriesChange},
+    webhook::Payload,
+};
+
+/// 繰り返しのタスクを、何日先の分まで作成しておくかです。
+const HORIZON_DAYS: i64 = 60;
+
+/// すべてのギルドの繰り返しのタスクについて、まだ作成していない回を作成します。
+///
+/// 失敗したギルドがあっても、ほかのギルドの分は作成します。
+pub fn materialize_all(data: &Data) -> Result<(), Error> {
+    let mut failed = 0;
+    for guild_id in data.guild_ids() {
+        if let Err(e) = materialize(data, guild_id) {
+            println!("{}: Failed to create recurring tasks: {:?}", guild_id, e);
+            failed += 1;
+        }
+    }
+    anyhow::ensure!(
+        failed == 0,
+        "Failed to create recurring tasks in {} guilds",
+        failed
+    );
+    Ok(())
+}
+
+/// ギルドの繰り返しのタスクについて、今日から`HORIZON_DAYS`日先までの、まだ作成していない回を作成します。
+pub fn materialize(data: &Data, guild_id: GuildId) -> Result<(), Error> {
+    let guild = data.guild(guild_id);
+    let tz = guild.timezone();
+    let today = Utc::now().with_timezone(&tz).date_naive();
+
+    // 同時に呼ばれても同じ回を二重に作成しないよう、作成する前に記録しておく
+    let mut changed = false;
+    let mut pending = vec![];
+    for series in guild.recurring_tasks.lock().unwrap().iter_mut() {
+        // 過ぎた回の記録は不要になる
+        let len = series.occurrences.len();
+        series.occurrences.retain(|date, _| today <= *date);
+        changed |= series.occurrences.len() < len;
+
+        let dates = series
+            .dates(today, today + Duration::days(HORIZON_DAYS))
+            .filter(|date| !series.occurrences.contains_key(date))
+            .collect::<Vec<_>>();
+        for date in dates {
+            let task = series.task(date, tz);
+            series.occurrences.insert(date, task.id);
+            pending.push((series.id, date, task));
+        }
+    }
+
+    let mut created = 0;
+    let mut result = Ok(());
+    for (_, _, task) in &pending {
+        if let Err(e) = data.storage.insert_task(guild_id, task) {
+            result = Err(e);
+            break;
+        }
+        data.notify(&Payload::change(guild_id, None, Some(task.clone())));
+        created += 1;
+    }
+    // 作成できなかった回は記録から外し、次の機会に作成し直す
+    if created < pending.len() {
+        let mut recurring_tasks = guild.recurring_tasks.lock().unwrap();
+        for (series_id, date, task) in &pending[created..] {
+            if let Some(series) = recurring_tasks
+                .iter_mut()
+                .find(|series| series.id == *series_id)
+                && series.occurrences.get(date) == Some(&task.id)
+            {
+                series.occurrences.remove(date);
+            }
+        }
+    }
+    if changed || !pending.is_empty() {
+        println!("{}: Created {} recurring tasks", guild_id, created);
+        data::save(data, guild_id)?;
+    }
+
+    result
+}
+
+/// タスクが繰り返しのタスクの回であれば、その繰り返しのIDと回の日付を返します。
+pub fn series_of(data: &Data, guild_id: GuildId, task_id: Uuid) -> Option<(Uuid, NaiveDate)> {
+    data.guild(guild_id)
+        .recurring_tasks
+        .lock()
+        .unwrap()
+        .iter()
+        .find_map(|series| {
+            series
+                .occurrences
+                .iter()
+                .find(|(_, id)| **id == task_id)
+                .map(|(date, _)| (series.id, *date))
+        })
+}
+
+/// 繰り返しの規則を、開始日を`offset`だけずらして`start`にしたものに合わせます。
+fn shift(recurrence: &Recurrence, start: NaiveDate, offset: Duration) -> Recurrence {
+    match recurrence {
+        Recurrence::Weekly(weekdays) => Recurrence::Weekly(
+            weekdays
+                .iter()
+                .map(|weekday| {
+                    (0..offset.num_days().rem_euclid(7)).fold(*weekday, |weekday, _| weekday.succ())
+                })
+                .sorted_by_key(|weekday| weekday.num_days_from_monday())
+                .collect(),
+        ),
+        Recurrence::Days(days) => Recurrence::Days(*days),
+        Recurrence::Monthly(_) => Recurrence::Monthly(start.day()),
+    }
+}
+
+/// 作成済みの回を除いて、繰り返しの内容が同じかどうかです。
+fn same_rule(a: &RecurringTask, b: &RecurringTask) -> bool {
+    let rule = |series: &RecurringTask| RecurringTask {
+        occurrences: BTreeMap::new(),
+        ..series.clone()
+    };
+    rule(a) == rule(b)
+}
+
+/// `modified`の回とそれ以降の回を、`modified`の内容に変更します。
+///
+/// 繰り返しはその回で分割し、それ以降は`modified`の日時から新しく繰り返します。
+/// 以降の回は同じ日数だけずらし、新しい繰り返しに合わない回は削除します。
+/// 変更は1つの記録にまとめるので、分割も含めて`undo`で取り消せます。
+pub fn edit_following(
+    data: &Data,
+    guild_id: GuildId,
+    user: UserId,
+    modified: &Task,
+) -> Result<(), Error> {
+    let guild = data.guild(guild_id);
+    let tz = guild.timezone();
+    let (series_id, date) =
+        series_of(data, guild_id, modified.id).context("Task is not a recurring task")?;
+    let start = modified.datetime.with_timezone(&tz);
+    let offset = start.date_naive() - date;
+    let existing = data
+        .storage
+        .tasks(guild_id)?
+        .into_iter()
+        .map(|task| task.id)
+        .collect::<BTreeSet<_>>();
+
+    let (mut series, puts, olds) = {
+        let mut recurring_tasks = guild.recurring_tasks.lock().unwrap();
+        let index = recurring_tasks
+            .iter()
+            .position(|series| series.id == series_id)
+            .context("Recurring task not found")?;
+        let before = recurring_tasks[index].clone();
+        let mut old = before.clone();
+
+        let mut new = RecurringTask {
+            id: Uuid::new_v4(),
+            category: modified.category.clone(),
+            subject: modified.subject.clone(),
+            details: modified.details.clone(),
+            time: start.time(),
+            start: start.date_naive(),
+            recurrence: shift(&old.recurrence, start.date_naive(), offset),
+            until: old.until.map(|until| until + offset),
+            exceptions: old
+                .exceptions
+                .split_off(&date)
+                .into_iter()
+                .map(|date| date + offset)
+                .collect(),
+            occurrences: BTreeMap::new(),
+        };
+        old.until = date.pred_opt();
+
+        let mut puts = vec![(modified.id, Some(modified.clone()))];
+        for (date, id) in old.occurrences.split_off(&date) {
+            let date = date + offset;
+            let fits = new.occurs_on(date);
+            if fits {
+                new.occurrences.insert(date, id);
+            }
+            // 1回だけ削除された回は、削除したままにする
+            if id != modified.id && existing.contains(&id) {
+                puts.push((
+                    id,
+                    fits.then(|| Task {
+                        id,
+                        ..new.task(date, tz)
+                    }),
+                ));
+            }
+        }
+        let olds = data.storage.put_tasks(guild_id, &puts)?;
+
+        let mut after = vec![];
+        if old.until.is_some_and(|until| until < old.start) {
+            recurring_tasks.remove(index);
+        } else {
+            recurring_tasks[index] = old.clone();
+            after.push(old);
+        }
+        recurring_tasks.push(new.clone());
+        after.push(new);
+        (
+            SeriesChange {
+                before,
+                after,
+                tasks: vec![],
+            },
+            puts,
+            olds,
+        )
+    };
+
+    let mut changes = olds.into_iter().zip(puts.into_iter().map(|(_, task)| task));
+    let (before, after) = changes.next().unwrap();
+    for (old, new) in changes {
+        if let Some(old) = old {
+            data.notify(&Payload::change(guild_id, Some(old.clone()), new.clone()));
+            series.tasks.push((old, new));
+        }
+    }
+    data.push_change(
+        guild_id,
+        Change {
+            series: Some(series),
+            ..Change::new(user, before, after)
+        },
+    )?;
+
+    // 日付をずらした分、先の回が足りなくなることがある
+    materialize(data, guild_id)
+}
+
+/// `edit_following`による繰り返しの分割と、あわせて編集したほかの回を元に戻します。
+///
+/// 後から変更された回や繰り返しがある場合は、何も変更せずにエラーを返します。
+pub fn revert(data: &Data, guild_id: GuildId, change: &SeriesChange) -> Result<(), Error> {
+    let guild = data.guild(guild_id);
+    let (puts, olds) = {
+        let mut recurring_tasks = guild.recurring_tasks.lock().unwrap();
+        let mut puts = vec![];
+        for after in &change.after {
+            let current = recurring_tasks
+                .iter()
+                .find(|series| series.id == after.id)
+                .filter(|current| same_rule(current, after))
+                .context("Recurring task has been changed since")?;
+            // 分割した後に作成された回は削除し、元の繰り返しで作成し直す
+            for id in current.occurrences.values() {
+                if !change.before.occurrences.values().contains(id) {
+                    puts.push((*id, None));
+                }
+            }
+        }
+        for (before, after) in &change.tasks {
+            anyhow::ensure!(
+                data.storage.task(guild_id, before.id)? == *after,
+                "Task has been changed since"
+            );
+            puts.push((before.id, Some(before.clone())));
+        }
+        let olds = data.storage.put_tasks(guild_id, &puts)?;
+
+        let index = recurring_tasks
+            .iter()
+            .position(|series| series.id == change.before.id)
+            .unwrap_or(recurring_tasks.len());
+        recurring_tasks.retain(|series| !change.after.iter().any(|after| after.id == series.id));
+        let index = index.min(recurring_tasks.len());
+        recurring_tasks.insert(index, change.before.clone());
+        (puts, olds)
+    };
+
+    for (old, (_, new)) in olds.into_iter().zip(puts) {
+        if old.is_some() || new.is_some() {
+            data.notify(&Payload::change(guild_id, old, new));
+        }
+    }
+    Ok(())
+}
+
+/// 繰り返しのタスクを削除し、今日以降の作成済みの回をゴミ箱に入れます。
+pub fn remove(
+    data: &Data,
+    guild_id: GuildId,
+    user: UserId,
+    id: Uuid,
+) -> Result<RecurringTask, Error> {
+    let guild = data.guild(guild_id);
+    let series = {
+        let mut recurring_tasks = guild.recurring_tasks.lock().unwrap();
+        let index = recurring_tasks
+            .iter()
+            .position(|series| series.id == id)
+            .context("Recurring task not found")?;
+        recurring_tasks.remove(index)
+    };
+
+    let now = Utc::now();
+    for id in series.occurrences.values() {
+        if let Some(task) = data.storage.task(guild_id, *id)?
+            && now <= task.datetime
+        {
+            data.remove_task(guild_id, user, task.id)?;
+        }
+    }
+    data::save(data, guild_id)?;
+
+    Ok(series)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Weekday;
+
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn shift_moves_weekdays_by_offset() {
+        let weekly = Recurrence::Weekly(vec![Weekday::Mon, Weekday::Sat]);
+        assert_eq!(
+            shift(&weekly, date(2025, 4, 8), Duration::days(1)),
+            Recurrence::Weekly(vec![Weekday::Tue, Weekday::Sun]),
+        );
+        assert_eq!(
+            shift(&weekly, date(2025, 4, 6), Duration::days(-1)),
+            Recurrence::Weekly(vec![Weekday::Fri, Weekday::Sun]),
+        );
+        assert_eq!(
+            shift(
+                &Recurrence::Weekly(vec![]),
+                date(2025, 4, 8),
+                Duration::days(3)
+            ),
+            Recurrence::Weekly(vec![]),
+        );
+    }
+
+    #[test]
+    fn shift_uses_new_day_of_month() {
+        assert_eq!(
+            shift(
+                &Recurrence::Monthly(10),
+                date(2025, 4, 12),
+                Duration::days(2)
+            ),
+            Recurrence::Monthly(12),
+        );
+        assert_eq!(
+            shift(&Recurrence::Days(3), date(2025, 4, 12), Duration::days(2)),
+            Recurrence::Days(3),
+        );
+    }
+}
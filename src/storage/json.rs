@@ -0,0 +1,264 @@
+use std::{collections::BTreeMap, fs, path::PathBuf, sync::Mutex};
+
+use anyhow::{Context as _, Error};
+use chrono::{DateTime, Utc};
+use itertools::Itertools;
+use poise::serenity_prelude::*;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    Task,
+    data::GuildData,
+    storage::{
+        self, Storage, migrations,
+        writer::{FileWriter, write_atomic},
+    },
+};
+
+#[derive(Serialize, Deserialize)]
+pub struct JsonFile {
+    pub version: u64,
+    pub guilds: BTreeMap<GuildId, JsonGuild>,
+}
+
+impl Default for JsonFile {
+    fn default() -> Self {
+        Self {
+            version: migrations::CURRENT_VERSION,
+            guilds: BTreeMap::new(),
+        }
+    }
+}
+
+/// ギルドの設定に`tasks`を加えたものです。
+#[derive(Serialize, Deserialize, Default)]
+pub struct JsonGuild {
+    pub tasks: BTreeMap<Uuid, Task>,
+    #[serde(flatten)]
+    pub config: serde_json::Map<String, serde_json::Value>,
+}
+
+/// すべてのデータを1つのJSONファイルに保存するバックエンドです。
+pub struct JsonStorage {
+    file: Mutex<JsonFile>,
+    writer: FileWriter,
+    _lock: fs::File,
+}
+
+impl JsonStorage {
+    /// `legacy_guild`は、ギルド単位に分かれる前の形式のデータを割り当てるギルドです。
+    pub fn open(path: impl Into<PathBuf>, legacy_guild: Option<GuildId>) -> Result<Self, Error> {
+        let path = path.into();
+        let lock = storage::lock(&path)?;
+        let file = match fs::read_to_string(&path) {
+            Ok(data) => {
+                let data: serde_json::Value =
+                    serde_json::from_str(&data).context("Failed to parse data.json")?;
+                let version = migrations::version(&data);
+                if version < migrations::CURRENT_VERSION {
+                    let backup = format!("{}.v{}.bak", path.display(), version);
+                    fs::copy(&path, &backup)?;
+                    println!("Backed up {} to {}", path.display(), backup);
+                }
+                let file = serde_json::from_value(migrations::migrate(data, legacy_guild)?)
+                    .context("Failed to parse data.json")?;
+                if version < migrations::CURRENT_VERSION {
+                    write_atomic(&path, serde_json::to_string(&file)?.as_bytes())?;
+                }
+                file
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                println!("Note: {} not found, using default data", path.display());
+                JsonFile::default()
+            }
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read {}", path.display()));
+            }
+        };
+        Ok(Self {
+            file: Mutex::new(file),
+            writer: FileWriter::spawn(path),
+            _lock: lock,
+        })
+    }
+
+    fn modify<T>(&self, f: impl FnOnce(&mut JsonFile) -> T) -> Result<T, Error> {
+        let (result, generation) = {
+            let mut file = self.file.lock().unwrap();
+            let result = f(&mut file);
+            (result, self.writer.enqueue(serde_json::to_string(&*file)?))
+        };
+        self.writer.wait(generation)?;
+        Ok(result)
+    }
+}
+
+impl Storage for JsonStorage {
+    fn load_guilds(&self) -> Result<BTreeMap<GuildId, GuildData>, Error> {
+        self.file
+            .lock()
+            .unwrap()
+            .guilds
+            .iter()
+            .map(|(guild_id, guild)| {
+                let config = serde_json::Value::Object(guild.config.clone());
+                Ok((*guild_id, serde_json::from_value(config)?))
+            })
+            .collect()
+    }
+
+    fn save_guild(&self, guild_id: GuildId, guild: &GuildData) -> Result<(), Error> {
+        let serde_json::Value::Object(config) = serde_json::to_value(guild)? else {
+            unreachable!()
+        };
+        self.modify(|file| file.guilds.entry(guild_id).or_default().config = config)
+    }
+
+    fn tasks(&self, guild_id: GuildId) -> Result<Vec<Task>, Error> {
+        Ok(self
+            .file
+            .lock()
+            .unwrap()
+            .guilds
+            .get(&guild_id)
+            .map(|guild| {
+                guild
+                    .tasks
+                    .values()
+                    .sorted_by_key(|task| task.datetime)
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    fn tasks_between(
+        &self,
+        guild_id: GuildId,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<Task>, Error> {
+        Ok(self
+            .tasks(guild_id)?
+            .into_iter()
+            .filter(|task| from <= task.datetime && task.datetime < to)
+            .collect())
+    }
+
+    fn task(&self, guild_id: GuildId, id: Uuid) -> Result<Option<Task>, Error> {
+        Ok(self
+            .file
+            .lock()
+            .unwrap()
+            .guilds
+            .get(&guild_id)
+            .and_then(|guild| guild.tasks.get(&id))
+            .cloned())
+    }
+
+    fn insert_task(&self, guild_id: GuildId, task: &Task) -> Result<(), Error> {
+        self.modify(|file| {
+            file.guilds
+                .entry(guild_id)
+                .or_default()
+                .tasks
+                .insert(task.id, task.clone());
+        })
+    }
+
+    fn update_task(&self, guild_id: GuildId, task: &Task) -> Result<Task, Error> {
+        self.modify(|file| {
+            file.guilds
+                .get_mut(&guild_id)
+                .and_then(|guild| guild.tasks.get_mut(&task.id))
+                .map(|old| std::mem::replace(old, task.clone()))
+                .context("Task not found")
+        })?
+    }
+
+    fn delete_task(&self, guild_id: GuildId, id: Uuid) -> Result<Task, Error> {
+        self.modify(|file| {
+            file.guilds
+                .get_mut(&guild_id)
+                .and_then(|guild| guild.tasks.remove(&id))
+                .context("Task not found")
+        })?
+    }
+
+    fn put_tasks(
+        &self,
+        guild_id: GuildId,
+        tasks: &[(Uuid, Option<Task>)],
+    ) -> Result<Vec<Option<Task>>, Error> {
+        self.modify(|file| {
+            let guild = file.guilds.entry(guild_id).or_default();
+            tasks
+                .iter()
+                .map(|(id, task)| match task {
+                    Some(task) => guild.tasks.insert(*id, task.clone()),
+                    None => guild.tasks.remove(id),
+                })
+                .collect()
+        })
+    }
+
+    fn replace_guild(
+        &self,
+        guild_id: GuildId,
+        guild: &GuildData,
+        tasks: &[Task],
+    ) -> Result<(), Error> {
+        let serde_json::Value::Object(config) = serde_json::to_value(guild)? else {
+            unreachable!()
+        };
+        let tasks = tasks.iter().map(|task| (task.id, task.clone())).collect();
+        self.modify(|file| {
+            file.guilds.insert(guild_id, JsonGuild { tasks, config });
+        })
+    }
+}
+
+/// `export`で書き出したバックアップから、指定したギルドの設定とタスクを読み込みます。
+///
+/// 古い形式のバックアップは、現在の形式に変換してから読み込みます。
+pub fn parse_backup(contents: &str, guild_id: GuildId) -> Result<(GuildData, Vec<Task>), Error> {
+    let data: serde_json::Value = serde_json::from_str(contents).context("Invalid JSON")?;
+    let mut file: JsonFile = serde_json::from_value(migrations::migrate(data, Some(guild_id))?)
+        .context("Invalid backup format")?;
+    let guild = file
+        .guilds
+        .remove(&guild_id)
+        .context("Backup does not contain this guild")?;
+    Ok((
+        serde_json::from_value(serde_json::Value::Object(guild.config))?,
+        guild.tasks.into_values().collect(),
+    ))
+}
+
+/// 指定したギルドのデータを、JSONバックエンドと同じ形式で書き出します。
+pub fn export(storage: &dyn Storage, guild_ids: &[GuildId]) -> Result<String, Error> {
+    let guilds = storage.load_guilds()?;
+    let mut file = JsonFile::default();
+    for guild_id in guild_ids {
+        let config = match guilds.get(guild_id) {
+            Some(guild) => serde_json::to_value(guild)?,
+            None => serde_json::to_value(GuildData::default())?,
+        };
+        let serde_json::Value::Object(config) = config else {
+            unreachable!()
+        };
+        file.guilds.insert(
+            *guild_id,
+            JsonGuild {
+                tasks: storage
+                    .tasks(*guild_id)?
+                    .into_iter()
+                    .map(|task| (task.id, task))
+                    .collect(),
+                config,
+            },
+        );
+    }
+    Ok(serde_json::to_string(&file)?)
+}
@@ -0,0 +1,262 @@
+use anyhow::{Context as _, Error};
+use poise::serenity_prelude::*;
+use serde_json::{Value, json};
+use uuid::Uuid;
+
+use crate::data::DEFAULT_WARN_OFFSETS;
+
+/// data.jsonの現在の形式のバージョンです。
+pub const CURRENT_VERSION: u64 = 3;
+
+type Migration = fn(Value, Option<GuildId>) -> Result<Value, Error>;
+
+/// `MIGRATIONS[i]`は、バージョン`i`から`i + 1`へのマイグレーションです。
+const MIGRATIONS: [Migration; CURRENT_VERSION as usize] = [v0_to_v1, v1_to_v2, v2_to_v3];
+
+/// `version`フィールドが付く前のファイルは、形式からバージョンを判定します。
+pub fn version(data: &Value) -> u64 {
+    match data.get("version").and_then(Value::as_u64) {
+        Some(version) => version,
+        None if data.get("guilds").is_some() => 1,
+        None => 0,
+    }
+}
+
+/// `data`を現在のバージョンの形式に変換します。
+///
+/// `legacy_guild`は、ギルド単位に分かれる前の形式のデータを割り当てるギルドです。
+pub fn migrate(mut data: Value, legacy_guild: Option<GuildId>) -> Result<Value, Error> {
+    let from = version(&data);
+    anyhow::ensure!(
+        from <= CURRENT_VERSION,
+        "data.json version {} is newer than supported version {}",
+        from,
+        CURRENT_VERSION
+    );
+
+    for (version, migration) in MIGRATIONS.iter().enumerate().skip(from as usize) {
+        println!("Migrating data.json: v{} -> v{}", version, version + 1);
+        data = migration(data, legacy_guild)?;
+    }
+    data["version"] = json!(CURRENT_VERSION);
+
+    Ok(data)
+}
+
+/// すべてのデータを、1つのギルドのデータとして割り当てます。
+fn v0_to_v1(data: Value, legacy_guild: Option<GuildId>) -> Result<Value, Error> {
+    let guild_id = legacy_guild.context("Cannot determine the guild for legacy data.json")?;
+    println!("Note: legacy data.json assigned to guild {}", guild_id);
+    Ok(json!({ "guilds": { guild_id.to_string(): data } }))
+}
+
+/// 各タスクにIDを振り、`tasks`をIDをキーとするマップにします。
+fn v1_to_v2(mut data: Value, _: Option<GuildId>) -> Result<Value, Error> {
+    for guild in data["guilds"]
+        .as_object_mut()
+        .context("guilds is not an object")?
+        .values_mut()
+    {
+        let tasks = match guild["tasks"].take() {
+            Value::Array(tasks) => tasks,
+            Value::Null => vec![],
+            _ => anyhow::bail!("tasks is not an array"),
+        };
+        guild["tasks"] = tasks
+            .into_iter()
+            .map(|mut task| {
+                let id = Uuid::new_v4().to_string();
+                task["id"] = json!(id);
+                (id, task)
+            })
+            .collect();
+    }
+    Ok(data)
+}
+
+/// 期限接近通知の設定を、ユーザーごとの購読設定にまとめます。
+fn v2_to_v3(mut data: Value, _: Option<GuildId>) -> Result<Value, Error> {
+    for guild in data["guilds"]
+        .as_object_mut()
+        .context("guilds is not an object")?
+        .values_mut()
+    {
+        merge_warn_users(guild)?;
+    }
+    Ok(data)
+}
+
+/// `warn_users`と`warn_offsets`を`subscriptions`に置き換えます。
+///
+/// これまでの通知は宿題のみだったため、購読する種類は宿題にします。
+pub fn merge_warn_users(config: &mut Value) -> Result<(), Error> {
+    let config = config
+        .as_object_mut()
+        .context("guild config is not an object")?;
+    let users = match config.remove("warn_users") {
+        Some(Value::Array(users)) => users,
+        None | Some(Value::Null) => vec![],
+        _ => anyhow::bail!("warn_users is not an array"),
+    };
+    let mut offsets = match config.remove("warn_offsets") {
+        Some(Value::Object(offsets)) => offsets,
+        None | Some(Value::Null) => Default::default(),
+        _ => anyhow::bail!("warn_offsets is not an object"),
+    };
+
+    let subscriptions = users
+        .into_iter()
+        .map(|user| {
+            let user = match user {
+                Value::String(user) => user,
+                user => user.to_string(),
+            };
+            let offsets = offsets
+                .remove(&user)
+                .unwrap_or_else(|| json!(DEFAULT_WARN_OFFSETS));
+            let subscription = json!({
+                "categories": ["Homework"],
+                "subjects": [],
+                "offsets": offsets,
+            });
+            (user, subscription)
+        })
+        .collect();
+    config.insert("subscriptions".to_string(), Value::Object(subscriptions));
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        data::GuildData,
+        storage::{JsonStorage, Storage, json::JsonFile},
+    };
+
+    const V0: &str = include_str!("../../tests/fixtures/data_v0.json");
+    const V1: &str = include_str!("../../tests/fixtures/data_v1.json");
+    const V2: &str = include_str!("../../tests/fixtures/data_v2.json");
+
+    const GUILD: GuildId = GuildId::new(1330000000000000000);
+
+    fn parse(data: &str) -> JsonFile {
+        let data = migrate(serde_json::from_str(data).unwrap(), Some(GUILD)).unwrap();
+        serde_json::from_value(data).unwrap()
+    }
+
+    fn config(file: &JsonFile) -> GuildData {
+        serde_json::from_value(Value::Object(file.guilds[&GUILD].config.clone())).unwrap()
+    }
+
+    fn assert_ids_assigned(file: &JsonFile) {
+        for (id, task) in &file.guilds[&GUILD].tasks {
+            assert_eq!(*id, task.id);
+        }
+    }
+
+    #[test]
+    fn detects_versions() {
+        assert_eq!(version(&serde_json::from_str(V0).unwrap()), 0);
+        assert_eq!(version(&serde_json::from_str(V1).unwrap()), 1);
+        assert_eq!(version(&serde_json::from_str(V2).unwrap()), 2);
+        assert_eq!(version(&json!({ "version": 5, "guilds": {} })), 5);
+    }
+
+    #[test]
+    fn migrates_v0() {
+        let file = parse(V0);
+        assert_eq!(file.version, CURRENT_VERSION);
+        assert_eq!(file.guilds.len(), 1);
+        assert_eq!(file.guilds[&GUILD].tasks.len(), 2);
+        let config = config(&file);
+        assert_eq!(config.subjects.lock().unwrap().len(), 2);
+        let subscriptions = config.subscriptions.lock().unwrap();
+        assert_eq!(subscriptions.len(), 1);
+        assert!(
+            subscriptions
+                .values()
+                .all(|subscription| *subscription == Default::default())
+        );
+        drop(subscriptions);
+        assert!(config.panel_message.lock().unwrap().is_some());
+        assert_ids_assigned(&file);
+    }
+
+    #[test]
+    fn migrates_v1() {
+        let file = parse(V1);
+        assert_eq!(file.version, CURRENT_VERSION);
+        assert_eq!(file.guilds[&GUILD].tasks.len(), 1);
+        assert!(config(&file).log_channel.lock().unwrap().is_some());
+        assert_ids_assigned(&file);
+    }
+
+    #[test]
+    fn migrates_v2() {
+        let file = parse(V2);
+        assert_eq!(file.version, CURRENT_VERSION);
+        let tasks = &file.guilds[&GUILD].tasks;
+        let id: Uuid = "5b0c4a3e-8f1d-4c2a-9e6b-7d3f2a1c0b9e".parse().unwrap();
+        assert_eq!(tasks[&id].details, "単語テスト");
+    }
+
+    #[test]
+    fn migrates_v2_warn_offsets() {
+        let data = json!({
+            "version": 2,
+            "guilds": {
+                GUILD.to_string(): {
+                    "tasks": {},
+                    "warn_users": ["1330000000000000010", "1330000000000000011"],
+                    "warn_offsets": { "1330000000000000010": [10, 1440] },
+                },
+            },
+        });
+        let file: JsonFile = serde_json::from_value(migrate(data, Some(GUILD)).unwrap()).unwrap();
+        assert_eq!(file.version, CURRENT_VERSION);
+        let config = config(&file);
+        let subscriptions = config.subscriptions.lock().unwrap();
+        assert_eq!(subscriptions.len(), 2);
+        assert_eq!(
+            subscriptions[&UserId::new(1330000000000000010)].offsets,
+            [10, 1440].into()
+        );
+        assert_eq!(
+            subscriptions[&UserId::new(1330000000000000011)],
+            Default::default()
+        );
+    }
+
+    #[test]
+    fn v0_requires_guild() {
+        assert!(migrate(serde_json::from_str(V0).unwrap(), None).is_err());
+    }
+
+    #[test]
+    fn rejects_newer_version() {
+        let data = json!({ "version": CURRENT_VERSION + 1, "guilds": {} });
+        assert!(migrate(data, Some(GUILD)).is_err());
+    }
+
+    #[test]
+    fn backs_up_before_migrating() {
+        let dir = std::env::temp_dir().join(format!("task-bot-rs-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("data.json");
+        std::fs::write(&path, V0).unwrap();
+
+        let storage = JsonStorage::open(&path, Some(GUILD)).unwrap();
+        assert_eq!(storage.tasks(GUILD).unwrap().len(), 2);
+        assert_eq!(
+            std::fs::read_to_string(dir.join("data.json.v0.bak")).unwrap(),
+            V0
+        );
+        let migrated: Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(version(&migrated), CURRENT_VERSION);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,118 @@
+use std::{
+    collections::BTreeMap,
+    fs::{File, TryLockError},
+    path::Path,
+    sync::Arc,
+};
+
+use anyhow::{Context as _, Error};
+use chrono::{DateTime, Utc};
+use poise::serenity_prelude::*;
+use uuid::Uuid;
+
+use crate::{Task, data::GuildData};
+
+mod json;
+pub use json::{JsonStorage, export, parse_backup};
+pub mod migrations;
+mod sqlite;
+mod writer;
+pub use sqlite::SqliteStorage;
+pub use writer::write_atomic;
+
+pub const JSON_PATH: &str = "data.json";
+pub const SQLITE_PATH: &str = "data.sqlite3";
+
+/// データの保存先です。
+///
+/// タスクはバックエンドが保持し、その他のギルドの設定はメモリ上の`GuildData`をまとめて保存します。
+pub trait Storage: Send + Sync {
+    /// 保存されているすべてのギルドの設定を読み込みます。
+    fn load_guilds(&self) -> Result<BTreeMap<GuildId, GuildData>, Error>;
+
+    fn save_guild(&self, guild_id: GuildId, guild: &GuildData) -> Result<(), Error>;
+
+    /// ギルドのタスクをすべて、日時順で取得します。
+    fn tasks(&self, guild_id: GuildId) -> Result<Vec<Task>, Error>;
+
+    /// `from <= datetime < to`のタスクを、日時順で取得します。
+    fn tasks_between(
+        &self,
+        guild_id: GuildId,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<Task>, Error>;
+
+    fn task(&self, guild_id: GuildId, id: Uuid) -> Result<Option<Task>, Error>;
+
+    fn insert_task(&self, guild_id: GuildId, task: &Task) -> Result<(), Error>;
+
+    /// IDが一致するタスクを置き換え、置き換える前のタスクを返します。
+    ///
+    /// タスクが存在しない場合はエラーになります。
+    fn update_task(&self, guild_id: GuildId, task: &Task) -> Result<Task, Error>;
+
+    /// タスクを削除し、削除したタスクを返します。
+    ///
+    /// タスクが存在しない場合はエラーになります。
+    fn delete_task(&self, guild_id: GuildId, id: Uuid) -> Result<Task, Error>;
+
+    /// 複数のタスクを、まとめて追加・置き換え・削除します。
+    ///
+    /// `tasks`はIDと変更後のタスクの組で、変更後が`None`の場合は削除します。
+    /// 変更前のタスクを、同じ順番で返します。
+    fn put_tasks(
+        &self,
+        guild_id: GuildId,
+        tasks: &[(Uuid, Option<Task>)],
+    ) -> Result<Vec<Option<Task>>, Error>;
+
+    /// ギルドの設定とタスクを、まとめて置き換えます。
+    fn replace_guild(
+        &self,
+        guild_id: GuildId,
+        guild: &GuildData,
+        tasks: &[Task],
+    ) -> Result<(), Error>;
+}
+
+/// `path`に対応するロックファイルの排他ロックを取得します。
+///
+/// 複数のプロセスが同じデータに書き込まないよう、返された`File`はバックエンドを閉じるまで保持してください。
+pub fn lock(path: &Path) -> Result<File, Error> {
+    let mut lock_path = path.as_os_str().to_owned();
+    lock_path.push(".lock");
+
+    let file = File::create(&lock_path)?;
+    match file.try_lock() {
+        Ok(()) => Ok(file),
+        Err(TryLockError::WouldBlock) => Err(anyhow::anyhow!(
+            "{} is locked by another process",
+            path.display()
+        )),
+        Err(TryLockError::Error(e)) => {
+            Err(e).with_context(|| format!("Failed to lock {}", path.display()))
+        }
+    }
+}
+
+/// 環境変数`STORAGE`(`json`または`sqlite`)に従ってバックエンドを開きます。
+///
+/// SQLiteのデータベースが空で`data.json`が存在する場合は、その内容を取り込みます。
+pub fn open(legacy_guild: Option<GuildId>) -> Result<Arc<dyn Storage>, Error> {
+    let backend = std::env::var("STORAGE").unwrap_or("json".into());
+    match backend.as_str() {
+        "json" => Ok(Arc::new(JsonStorage::open(JSON_PATH, legacy_guild)?)),
+        "sqlite" => {
+            let storage = SqliteStorage::open(SQLITE_PATH)?;
+            if storage.is_empty()? && Path::new(JSON_PATH).exists() {
+                println!("Importing {} into {}", JSON_PATH, SQLITE_PATH);
+                // 途中で失敗しても取り込み直せるよう、すべて書き込めてから名前を変える
+                storage.import(&JsonStorage::open(JSON_PATH, legacy_guild)?)?;
+                std::fs::rename(JSON_PATH, format!("{}.imported", JSON_PATH))?;
+            }
+            Ok(Arc::new(storage))
+        }
+        _ => anyhow::bail!("Unknown storage backend: {}", backend),
+    }
+}
@@ -0,0 +1,379 @@
+use std::{collections::BTreeMap, fs::File, path::Path, sync::Mutex};
+
+use anyhow::{Context as _, Error};
+use chrono::{DateTime, TimeZone, Utc};
+use poise::serenity_prelude::*;
+use rusqlite::{Connection, OptionalExtension, Row, params};
+use uuid::Uuid;
+
+use crate::{
+    Category, Subject, Task,
+    data::GuildData,
+    storage::{self, Storage, migrations},
+};
+
+type SchemaMigration = fn(&Connection) -> Result<(), Error>;
+
+/// `SCHEMA[i]`は、スキーマのバージョン`i`から`i + 1`へのマイグレーションです。
+///
+/// 現在のバージョンは`PRAGMA user_version`に記録します。
+const SCHEMA: [SchemaMigration; 4] = [
+    |conn| {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS guilds (
+                guild_id INTEGER PRIMARY KEY,
+                config TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS tasks (
+                guild_id INTEGER NOT NULL,
+                category TEXT NOT NULL,
+                subject TEXT,
+                details TEXT NOT NULL,
+                datetime INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS tasks_guild_datetime ON tasks (guild_id, datetime);",
+        )?;
+        Ok(())
+    },
+    // 既存のタスクにIDを振る
+    |conn| {
+        conn.execute_batch("ALTER TABLE tasks ADD COLUMN id TEXT")?;
+        let rowids = conn
+            .prepare("SELECT rowid FROM tasks")?
+            .query_map([], |row| row.get::<_, i64>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        for rowid in rowids {
+            conn.execute(
+                "UPDATE tasks SET id = ?1 WHERE rowid = ?2",
+                params![Uuid::new_v4().to_string(), rowid],
+            )?;
+        }
+        conn.execute_batch("CREATE UNIQUE INDEX tasks_id ON tasks (id)")?;
+        Ok(())
+    },
+    // 期限接近通知の設定を購読設定にまとめる
+    |conn| {
+        let configs = conn
+            .prepare("SELECT guild_id, config FROM guilds")?
+            .query_map([], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        for (guild_id, config) in configs {
+            let mut config = serde_json::from_str(&config)?;
+            migrations::merge_warn_users(&mut config)?;
+            conn.execute(
+                "UPDATE guilds SET config = ?1 WHERE guild_id = ?2",
+                params![serde_json::to_string(&config)?, guild_id],
+            )?;
+        }
+        Ok(())
+    },
+    // タスクのIDはギルドごとに一意にする
+    |conn| {
+        conn.execute_batch(
+            "DROP INDEX tasks_id;
+            CREATE UNIQUE INDEX tasks_guild_id ON tasks (guild_id, id);",
+        )?;
+        Ok(())
+    },
+];
+
+/// 埋め込みのSQLiteデータベースに保存するバックエンドです。
+///
+/// タスクは1行ずつ`tasks`テーブルに、ギルドの設定はJSONとして`guilds`テーブルに保存します。
+pub struct SqliteStorage {
+    conn: Mutex<Connection>,
+    _lock: File,
+}
+
+impl SqliteStorage {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let lock = storage::lock(path.as_ref())?;
+        let mut conn = Connection::open(path)?;
+
+        let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        for (version, migration) in SCHEMA.iter().enumerate().skip(version) {
+            println!(
+                "Migrating database schema: v{} -> v{}",
+                version,
+                version + 1
+            );
+            let transaction = conn.transaction()?;
+            migration(&transaction)?;
+            transaction.pragma_update(None, "user_version", version + 1)?;
+            transaction.commit()?;
+        }
+
+        Ok(Self {
+            conn: Mutex::new(conn),
+            _lock: lock,
+        })
+    }
+
+    pub fn is_empty(&self) -> Result<bool, Error> {
+        let conn = self.conn.lock().unwrap();
+        let exists: bool = conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM guilds) OR EXISTS (SELECT 1 FROM tasks)",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok(!exists)
+    }
+
+    /// `from`の内容をすべて、1つのトランザクションで取り込みます。
+    pub fn import(&self, from: &dyn Storage) -> Result<(), Error> {
+        let mut conn = self.conn.lock().unwrap();
+        let transaction = conn.transaction()?;
+        for (guild_id, guild) in from.load_guilds()? {
+            save_guild(&transaction, guild_id, &guild)?;
+            for task in from.tasks(guild_id)? {
+                insert_task(&transaction, guild_id, &task)?;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn query_tasks(&self, sql: &str, params: impl rusqlite::Params) -> Result<Vec<Task>, Error> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn.prepare(sql)?;
+        let rows = statement.query_map(params, |row| Ok(TaskRow::from_row(row)))?;
+        rows.map(|row| row?.into_task()).collect()
+    }
+}
+
+struct TaskRow {
+    id: String,
+    category: String,
+    subject: Option<String>,
+    details: String,
+    datetime: i64,
+}
+
+impl TaskRow {
+    fn from_row(row: &Row) -> Self {
+        Self {
+            id: row.get_unwrap("id"),
+            category: row.get_unwrap("category"),
+            subject: row.get_unwrap("subject"),
+            details: row.get_unwrap("details"),
+            datetime: row.get_unwrap("datetime"),
+        }
+    }
+
+    fn from_task(task: &Task) -> Result<Self, Error> {
+        Ok(Self {
+            id: task.id.to_string(),
+            category: task.category.0.clone(),
+            subject: match &task.subject {
+                Subject::Set(s) => Some(s.clone()),
+                Subject::Unset => None,
+            },
+            details: task.details.clone(),
+            datetime: task.datetime.timestamp(),
+        })
+    }
+
+    fn into_task(self) -> Result<Task, Error> {
+        Ok(Task {
+            category: Category(self.category),
+            subject: match self.subject {
+                Some(s) => Subject::Set(s),
+                None => Subject::Unset,
+            },
+            details: self.details,
+            datetime: Utc
+                .timestamp_opt(self.datetime, 0)
+                .single()
+                .context("Invalid timestamp")?,
+            id: self.id.parse()?,
+        })
+    }
+}
+
+const TASK_COLUMNS: &str = "id, category, subject, details, datetime";
+
+fn task(conn: &Connection, guild_id: GuildId, id: Uuid) -> Result<Option<Task>, Error> {
+    conn.query_row(
+        &format!("SELECT {TASK_COLUMNS} FROM tasks WHERE guild_id = ?1 AND id = ?2"),
+        params![guild_id.get() as i64, id.to_string()],
+        |row| Ok(TaskRow::from_row(row)),
+    )
+    .optional()?
+    .map(TaskRow::into_task)
+    .transpose()
+}
+
+fn save_guild(conn: &Connection, guild_id: GuildId, guild: &GuildData) -> Result<(), Error> {
+    conn.execute(
+        "INSERT INTO guilds (guild_id, config) VALUES (?1, ?2)
+        ON CONFLICT (guild_id) DO UPDATE SET config = excluded.config",
+        params![guild_id.get() as i64, serde_json::to_string(guild)?],
+    )?;
+    Ok(())
+}
+
+fn insert_task(conn: &Connection, guild_id: GuildId, task: &Task) -> Result<(), Error> {
+    let row = TaskRow::from_task(task)?;
+    conn.execute(
+        &format!("INSERT INTO tasks (guild_id, {TASK_COLUMNS}) VALUES (?1, ?2, ?3, ?4, ?5, ?6)"),
+        params![
+            guild_id.get() as i64,
+            row.id,
+            row.category,
+            row.subject,
+            row.details,
+            row.datetime
+        ],
+    )?;
+    Ok(())
+}
+
+impl Storage for SqliteStorage {
+    fn load_guilds(&self) -> Result<BTreeMap<GuildId, GuildData>, Error> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn.prepare("SELECT guild_id, config FROM guilds")?;
+        let rows = statement.query_map([], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+        })?;
+        rows.map(|row| {
+            let (guild_id, config) = row?;
+            Ok((
+                GuildId::new(guild_id as u64),
+                serde_json::from_str(&config)?,
+            ))
+        })
+        .collect()
+    }
+
+    fn save_guild(&self, guild_id: GuildId, guild: &GuildData) -> Result<(), Error> {
+        save_guild(&self.conn.lock().unwrap(), guild_id, guild)
+    }
+
+    fn tasks(&self, guild_id: GuildId) -> Result<Vec<Task>, Error> {
+        self.query_tasks(
+            &format!("SELECT {TASK_COLUMNS} FROM tasks WHERE guild_id = ?1 ORDER BY datetime"),
+            params![guild_id.get() as i64],
+        )
+    }
+
+    fn tasks_between(
+        &self,
+        guild_id: GuildId,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<Task>, Error> {
+        self.query_tasks(
+            &format!(
+                "SELECT {TASK_COLUMNS} FROM tasks
+                WHERE guild_id = ?1 AND ?2 <= datetime AND datetime < ?3
+                ORDER BY datetime"
+            ),
+            params![guild_id.get() as i64, from.timestamp(), to.timestamp()],
+        )
+    }
+
+    fn task(&self, guild_id: GuildId, id: Uuid) -> Result<Option<Task>, Error> {
+        task(&self.conn.lock().unwrap(), guild_id, id)
+    }
+
+    fn insert_task(&self, guild_id: GuildId, task: &Task) -> Result<(), Error> {
+        insert_task(&self.conn.lock().unwrap(), guild_id, task)
+    }
+
+    fn update_task(&self, guild_id: GuildId, new: &Task) -> Result<Task, Error> {
+        let mut conn = self.conn.lock().unwrap();
+        let transaction = conn.transaction()?;
+        let old = task(&transaction, guild_id, new.id)?.context("Task not found")?;
+        let row = TaskRow::from_task(new)?;
+        transaction.execute(
+            "UPDATE tasks SET category = ?3, subject = ?4, details = ?5, datetime = ?6
+            WHERE guild_id = ?1 AND id = ?2",
+            params![
+                guild_id.get() as i64,
+                row.id,
+                row.category,
+                row.subject,
+                row.details,
+                row.datetime
+            ],
+        )?;
+        transaction.commit()?;
+        Ok(old)
+    }
+
+    fn delete_task(&self, guild_id: GuildId, id: Uuid) -> Result<Task, Error> {
+        let mut conn = self.conn.lock().unwrap();
+        let transaction = conn.transaction()?;
+        let old = task(&transaction, guild_id, id)?.context("Task not found")?;
+        transaction.execute(
+            "DELETE FROM tasks WHERE guild_id = ?1 AND id = ?2",
+            params![guild_id.get() as i64, id.to_string()],
+        )?;
+        transaction.commit()?;
+        Ok(old)
+    }
+
+    fn put_tasks(
+        &self,
+        guild_id: GuildId,
+        tasks: &[(Uuid, Option<Task>)],
+    ) -> Result<Vec<Option<Task>>, Error> {
+        let mut conn = self.conn.lock().unwrap();
+        let transaction = conn.transaction()?;
+        let mut olds = vec![];
+        for (id, new) in tasks {
+            olds.push(task(&transaction, guild_id, *id)?);
+            match new {
+                Some(new) => {
+                    let row = TaskRow::from_task(new)?;
+                    transaction.execute(
+                        &format!(
+                            "INSERT INTO tasks (guild_id, {TASK_COLUMNS}) VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                            ON CONFLICT (guild_id, id) DO UPDATE SET
+                            category = excluded.category, subject = excluded.subject,
+                            details = excluded.details, datetime = excluded.datetime"
+                        ),
+                        params![
+                            guild_id.get() as i64,
+                            row.id,
+                            row.category,
+                            row.subject,
+                            row.details,
+                            row.datetime
+                        ],
+                    )?;
+                }
+                None => {
+                    transaction.execute(
+                        "DELETE FROM tasks WHERE guild_id = ?1 AND id = ?2",
+                        params![guild_id.get() as i64, id.to_string()],
+                    )?;
+                }
+            }
+        }
+        transaction.commit()?;
+        Ok(olds)
+    }
+
+    fn replace_guild(
+        &self,
+        guild_id: GuildId,
+        guild: &GuildData,
+        tasks: &[Task],
+    ) -> Result<(), Error> {
+        let mut conn = self.conn.lock().unwrap();
+        let transaction = conn.transaction()?;
+        save_guild(&transaction, guild_id, guild)?;
+        transaction.execute(
+            "DELETE FROM tasks WHERE guild_id = ?1",
+            params![guild_id.get() as i64],
+        )?;
+        for task in tasks {
+            insert_task(&transaction, guild_id, task)?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+}
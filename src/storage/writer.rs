@@ -0,0 +1,107 @@
+use std::{
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, Condvar, Mutex},
+    thread,
+};
+
+use anyhow::{Error, anyhow};
+
+struct State {
+    /// まだ書き込まれていない最新の内容と、その世代
+    pending: Option<(u64, String)>,
+    generation: u64,
+    /// 最後に書き込んだ世代と、その結果
+    written: (u64, Result<(), String>),
+}
+
+/// ファイルへの書き込みを1つのスレッドにまとめます。
+///
+/// 書き込み待ちの内容は最新のもので上書きされるので、常に最新の状態が最後に書き込まれます。
+pub struct FileWriter {
+    state: Arc<(Mutex<State>, Condvar)>,
+}
+
+impl FileWriter {
+    pub fn spawn(path: PathBuf) -> Self {
+        let state = Arc::new((
+            Mutex::new(State {
+                pending: None,
+                generation: 0,
+                written: (0, Ok(())),
+            }),
+            Condvar::new(),
+        ));
+
+        let thread_state = state.clone();
+        thread::spawn(move || {
+            let (state, condvar) = &*thread_state;
+            loop {
+                let (generation, contents) = {
+                    let mut state = condvar
+                        .wait_while(state.lock().unwrap(), |s| s.pending.is_none())
+                        .unwrap();
+                    state.pending.take().unwrap()
+                };
+                let result = write_atomic(&path, contents.as_bytes()).map_err(|e| {
+                    println!("Failed to write {}: {:?}", path.display(), e);
+                    e.to_string()
+                });
+                state.lock().unwrap().written = (generation, result);
+                condvar.notify_all();
+            }
+        });
+
+        Self { state }
+    }
+
+    /// 内容を書き込み待ちにし、その世代を返します。
+    ///
+    /// 書き込む順番がデータを変更した順番と一致するよう、データのロックを保持したまま呼び出してください。
+    pub fn enqueue(&self, contents: String) -> u64 {
+        let (state, condvar) = &*self.state;
+        let mut state = state.lock().unwrap();
+        state.generation += 1;
+        state.pending = Some((state.generation, contents));
+        condvar.notify_all();
+        state.generation
+    }
+
+    /// `generation`以降の内容が書き込まれるまで待ちます。
+    ///
+    /// 非同期のタスクから呼ばれるので、待っている間はほかのタスクをほかのスレッドに任せます。
+    pub fn wait(&self, generation: u64) -> Result<(), Error> {
+        let (state, condvar) = &*self.state;
+        tokio::task::block_in_place(|| {
+            let state = condvar
+                .wait_while(state.lock().unwrap(), |s| s.written.0 < generation)
+                .unwrap();
+            state.written.1.clone().map_err(|e| anyhow!(e))
+        })
+    }
+}
+
+/// 一時ファイルに書き込んでfsyncしてから置き換えることで、書き込み途中のファイルが残らないようにします。
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Result<(), Error> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+
+    let mut tmp = File::create(&tmp_path)?;
+    tmp.write_all(contents)?;
+    tmp.sync_all()?;
+    fs::rename(&tmp_path, path)?;
+
+    // リネームを永続化する
+    // `data.json`のような相対パスの親は空なので、カレントディレクトリとして扱う
+    if let Some(dir) = path.parent() {
+        let dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+        File::open(dir)?.sync_all()?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,124 @@
+use anyhow::Error;
+use poise::serenity_prelude::*;
+
+use crate::{
+    Subject, Task,
+    data::{self, BelongingTarget, Data},
+    webhook::Payload,
+};
+
+/// `subject`が設定されているタスクの数を返します。
+pub fn usage(data: &Data, guild_id: GuildId, subject: &str) -> Result<usize, Error> {
+    let subject = Subject::Set(subject.to_string());
+    Ok(data
+        .storage
+        .tasks(guild_id)?
+        .iter()
+        .filter(|task| task.subject == subject)
+        .count())
+}
+
+/// 教科`from`を削除し、`from`を参照しているタスクや設定を`to`に置き換えます。
+/// 置き換えたタスクの数を返します。
+///
+/// `to`が`None`の場合、タスクと繰り返しのタスクは教科なしになり、時間割のコマと
+/// その教科の日の常備の持ち物は削除されます。
+/// 購読の設定とカレンダーのフィードは、該当するタスクがなくなるだけなのでそのまま残します。
+pub fn replace(
+    data: &Data,
+    guild_id: GuildId,
+    from: &str,
+    to: Option<&str>,
+) -> Result<usize, Error> {
+    let guild = data.guild(guild_id);
+    let old = Subject::Set(from.to_string());
+    let new = to.map_or(Subject::Unset, |to| Subject::Set(to.to_string()));
+
+    // 一括の置き換えはゴミ箱を埋めてしまうので、履歴には記録しない
+    // 途中で失敗して一部のタスクだけが残らないよう、まとめて置き換える
+    let tasks = data
+        .storage
+        .tasks(guild_id)?
+        .into_iter()
+        .filter(|task| task.subject == old)
+        .map(|task| {
+            let task = Task {
+                subject: new.clone(),
+                ..task
+            };
+            (task.id, Some(task))
+        })
+        .collect::<Vec<_>>();
+    let befores = data.storage.put_tasks(guild_id, &tasks)?;
+    for (before, (_, task)) in befores.into_iter().zip(&tasks) {
+        data.notify(&Payload::change(guild_id, before, task.clone()));
+    }
+    let count = tasks.len();
+
+    {
+        let mut subjects = guild.subjects.lock().unwrap();
+        subjects.remove(from);
+        if let Some(to) = to {
+            subjects.insert(to.to_string());
+        }
+    }
+    // ゴミ箱からの復元や取り消しで、削除した教科が戻らないようにする
+    for change in guild.history.lock().unwrap().iter_mut() {
+        for subject in change.subjects_mut() {
+            if *subject == old {
+                *subject = new.clone();
+            }
+        }
+    }
+    for series in guild.recurring_tasks.lock().unwrap().iter_mut() {
+        if series.subject == old {
+            series.subject = new.clone();
+        }
+    }
+    match to {
+        Some(to) => {
+            for period in guild.timetable.lock().unwrap().iter_mut() {
+                if period.subject == from {
+                    period.subject = to.to_string();
+                }
+            }
+            for belonging in guild.standing_belongings.lock().unwrap().iter_mut() {
+                if belonging.target == BelongingTarget::Subject(from.to_string()) {
+                    belonging.target = BelongingTarget::Subject(to.to_string());
+                }
+            }
+            for subscription in guild.subscriptions.lock().unwrap().values_mut() {
+                if subscription.subjects.remove(from) {
+                    subscription.subjects.insert(to.to_string());
+                }
+            }
+            for feed in guild.calendar_feeds.lock().unwrap().iter_mut() {
+                if feed.subject.as_deref() == Some(from) {
+                    feed.subject = Some(to.to_string());
+                }
+            }
+        }
+        None => {
+            guild
+                .timetable
+                .lock()
+                .unwrap()
+                .retain(|period| period.subject != from);
+            guild
+                .standing_belongings
+                .lock()
+                .unwrap()
+                .retain(|belonging| belonging.target != BelongingTarget::Subject(from.to_string()));
+        }
+    }
+    guild.import_rules.lock().unwrap().retain_mut(|rule| {
+        if rule.subject.as_deref() == Some(from) {
+            rule.subject = to.map(str::to_string);
+        }
+        // 推測するものがなくなった規則は削除する
+        rule.category.is_some() || rule.subject.is_some()
+    });
+    data::save(data, guild_id)?;
+
+    Ok(count)
+}
@@ -0,0 +1,15 @@
+use crate::{PoiseContext, utilities::guild_data};
+
+/// 登録されている種類から候補を返します。
+pub async fn autocomplete_category<'a>(ctx: PoiseContext<'a>, partial: &'a str) -> Vec<String> {
+    let Ok((_, guild)) = guild_data(ctx) else {
+        return vec![];
+    };
+    guild
+        .categories()
+        .into_iter()
+        .filter(|category| category.name.contains(partial))
+        .take(25)
+        .map(|category| category.name)
+        .collect()
+}
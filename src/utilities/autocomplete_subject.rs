@@ -0,0 +1,17 @@
+use crate::{PoiseContext, utilities::guild_data};
+
+/// 登録されている教科から候補を返します。
+pub async fn autocomplete_subject<'a>(ctx: PoiseContext<'a>, partial: &'a str) -> Vec<String> {
+    let Ok((_, guild)) = guild_data(ctx) else {
+        return vec![];
+    };
+    guild
+        .subjects
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|subject| subject.contains(partial))
+        .take(25)
+        .cloned()
+        .collect()
+}
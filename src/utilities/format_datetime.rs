@@ -1,7 +1,9 @@
-use chrono::{DateTime, Local};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
 
-pub fn format_datetime(datetime: DateTime<Local>) -> String {
+pub fn format_datetime(datetime: DateTime<Utc>, tz: Tz) -> String {
     datetime
+        .with_timezone(&tz)
         .format("%Y/%m/%d (%a) %H:%M")
         .to_string()
         .replace("Sun", "日")
@@ -0,0 +1,18 @@
+/// `90`を`1時間30分`のように、分数を日・時間・分で表します。
+pub fn format_minutes(minutes: u32) -> String {
+    let parts = [
+        (minutes / (60 * 24), "日"),
+        (minutes / 60 % 24, "時間"),
+        (minutes % 60, "分"),
+    ];
+    let formatted = parts
+        .iter()
+        .filter(|(amount, _)| 0 < *amount)
+        .map(|(amount, unit)| format!("{}{}", amount, unit))
+        .collect::<String>();
+    if formatted.is_empty() {
+        "0分".into()
+    } else {
+        formatted
+    }
+}
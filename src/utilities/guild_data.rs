@@ -0,0 +1,11 @@
+use std::sync::Arc;
+
+use anyhow::{Context as _, Error};
+use poise::serenity_prelude::*;
+
+use crate::{PoiseContext, data::GuildData};
+
+pub fn guild_data(ctx: PoiseContext<'_>) -> Result<(GuildId, Arc<GuildData>), Error> {
+    let guild_id = ctx.guild_id().context("Not in a guild")?;
+    Ok((guild_id, ctx.data().guild(guild_id)))
+}
@@ -4,3 +4,17 @@ mod format_datetime;
 pub use format_datetime::format_datetime;
 mod responsive_interaction;
 pub use responsive_interaction::ResponsiveInteraction;
+mod guild_data;
+pub use guild_data::guild_data;
+mod autocomplete_subject;
+pub use autocomplete_subject::autocomplete_subject;
+mod autocomplete_category;
+pub use autocomplete_category::autocomplete_category;
+mod parse_date;
+pub use parse_date::{parse_date, parse_naive_date};
+mod timezone;
+pub use timezone::{default_timezone, to_utc};
+mod weekday;
+pub use weekday::{format_weekday, parse_weekdays};
+mod format_minutes;
+pub use format_minutes::format_minutes;
@@ -0,0 +1,17 @@
+use anyhow::{Context as _, Error};
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use chrono_tz::Tz;
+
+use crate::utilities::to_utc;
+
+/// `2025-02-06`または`2025/02/06`形式の日付の、`tz`での0時を返します。
+pub fn parse_date(date: &str, tz: Tz) -> Result<DateTime<Utc>, Error> {
+    Ok(to_utc(tz, parse_naive_date(date)?.and_time(NaiveTime::MIN)))
+}
+
+/// `2025-02-06`または`2025/02/06`形式の日付を読み取ります。
+pub fn parse_naive_date(date: &str) -> Result<NaiveDate, Error> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .or_else(|_| NaiveDate::parse_from_str(date, "%Y/%m/%d"))
+        .with_context(|| format!("Invalid date: {}", date))
+}
@@ -0,0 +1,68 @@
+use chrono::{DateTime, Duration, LocalResult, NaiveDateTime, Offset, TimeZone, Utc};
+use chrono_tz::Tz;
+
+/// タイムゾーンを設定していないギルドのタイムゾーンです。
+///
+/// 環境変数`DEFAULT_TIMEZONE`で変更でき、設定されていなければ`Asia/Tokyo`です。
+pub fn default_timezone() -> Tz {
+    std::env::var("DEFAULT_TIMEZONE")
+        .ok()
+        .and_then(|tz| tz.parse().ok())
+        .unwrap_or(chrono_tz::Asia::Tokyo)
+}
+
+/// `tz`での日時をUTCに変換します。
+///
+/// 夏時間の終わりで2回現れる時刻は、早い方として扱います。
+/// 夏時間の始まりで存在しない時刻は、切り替え前の時差で解釈します(時計が進んだ分だけ後の時刻になります)。
+pub fn to_utc(tz: Tz, naive: NaiveDateTime) -> DateTime<Utc> {
+    match tz.from_local_datetime(&naive) {
+        LocalResult::Single(datetime) | LocalResult::Ambiguous(datetime, _) => datetime.to_utc(),
+        LocalResult::None => {
+            let offset = tz
+                .offset_from_utc_datetime(&(naive - Duration::days(1)))
+                .fix();
+            (naive - offset).and_utc()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    fn naive(y: i32, m: u32, d: u32, h: u32, min: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(y, m, d)
+            .unwrap()
+            .and_hms_opt(h, min, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn converts_unambiguous_time() {
+        assert_eq!(
+            to_utc(chrono_tz::Asia::Tokyo, naive(2025, 4, 1, 9, 0)),
+            naive(2025, 4, 1, 0, 0).and_utc()
+        );
+    }
+
+    #[test]
+    fn picks_earlier_of_ambiguous_time() {
+        // 2025-11-02 01:30 はニューヨークで2回現れる(EDTとEST)
+        assert_eq!(
+            to_utc(chrono_tz::America::New_York, naive(2025, 11, 2, 1, 30)),
+            naive(2025, 11, 2, 5, 30).and_utc()
+        );
+    }
+
+    #[test]
+    fn shifts_nonexistent_time_forward() {
+        // 2025-03-09 02:30 はニューヨークに存在しないので、EST(-5:00)で解釈して03:30 EDTになる
+        assert_eq!(
+            to_utc(chrono_tz::America::New_York, naive(2025, 3, 9, 2, 30)),
+            naive(2025, 3, 9, 7, 30).and_utc()
+        );
+    }
+}
@@ -0,0 +1,78 @@
+use anyhow::{Error, bail};
+use chrono::Weekday;
+
+const WEEKDAYS: [Weekday; 7] = [
+    Weekday::Mon,
+    Weekday::Tue,
+    Weekday::Wed,
+    Weekday::Thu,
+    Weekday::Fri,
+    Weekday::Sat,
+    Weekday::Sun,
+];
+
+pub fn format_weekday(weekday: Weekday) -> &'static str {
+    match weekday {
+        Weekday::Mon => "月",
+        Weekday::Tue => "火",
+        Weekday::Wed => "水",
+        Weekday::Thu => "木",
+        Weekday::Fri => "金",
+        Weekday::Sat => "土",
+        Weekday::Sun => "日",
+    }
+}
+
+/// `月水金`のような曜日の並びを読み込みます。`毎日`は空、`平日`は月曜日から金曜日を返します。
+pub fn parse_weekdays(weekdays: &str) -> Result<Vec<Weekday>, Error> {
+    match weekdays.trim() {
+        "" | "毎日" => return Ok(vec![]),
+        "平日" => return Ok(WEEKDAYS[..5].to_vec()),
+        _ => {}
+    }
+
+    let mut parsed = vec![];
+    for c in weekdays.chars().filter(|c| !matches!(c, ' ' | ',' | '、')) {
+        let Some(weekday) = WEEKDAYS
+            .into_iter()
+            .find(|weekday| format_weekday(*weekday).starts_with(c))
+        else {
+            bail!("Invalid weekday: {}", c);
+        };
+        if !parsed.contains(&weekday) {
+            parsed.push(weekday);
+        }
+    }
+    parsed.sort_by_key(|weekday| weekday.num_days_from_monday());
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_keywords() {
+        assert_eq!(parse_weekdays("毎日").unwrap(), vec![]);
+        assert_eq!(parse_weekdays("").unwrap(), vec![]);
+        assert_eq!(
+            parse_weekdays("平日").unwrap(),
+            [
+                Weekday::Mon,
+                Weekday::Tue,
+                Weekday::Wed,
+                Weekday::Thu,
+                Weekday::Fri
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_weekday_characters() {
+        assert_eq!(
+            parse_weekdays("金、月水").unwrap(),
+            [Weekday::Mon, Weekday::Wed, Weekday::Fri]
+        );
+        assert!(parse_weekdays("月曜").is_err());
+    }
+}
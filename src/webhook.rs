@@ -0,0 +1,274 @@
+//! タスクの変更などを外部のサービスに通知するWebhookです。
+//!
+//! 本文のHMAC-SHA256署名を`X-Task-Bot-Signature`ヘッダーに付けてPOSTします。
+//! 送信に失敗したものは間隔を空けながら再送し、再起動しても失われないよう
+//! 送信待ちのものをファイル(`WEBHOOK_QUEUE_PATH`)に保存します。
+
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{Context as _, Error};
+use chrono::{DateTime, Duration, Utc};
+use hmac::{Hmac, Mac};
+use poise::serenity_prelude::GuildId;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tokio::{sync::Notify, time::timeout};
+use uuid::Uuid;
+
+use crate::{Task, data::Data, storage};
+
+/// これを超えて失敗した場合は送信を諦めます。
+const MAX_ATTEMPTS: u32 = 10;
+const MAX_BACKOFF: Duration = Duration::hours(6);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    TaskCreated,
+    TaskEdited,
+    TaskDeleted,
+    PingSent,
+}
+
+impl EventKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            EventKind::TaskCreated => "task_created",
+            EventKind::TaskEdited => "task_edited",
+            EventKind::TaskDeleted => "task_deleted",
+            EventKind::PingSent => "ping_sent",
+        }
+    }
+}
+
+/// Webhookで送る本文です。
+#[derive(Serialize, Debug)]
+pub struct Payload {
+    pub id: Uuid,
+    pub event: EventKind,
+    pub guild_id: GuildId,
+    pub datetime: DateTime<Utc>,
+    /// 変更前のタスク(`task_edited`と`task_deleted`)
+    pub before: Option<Task>,
+    /// 変更後のタスク(`task_created`と`task_edited`)
+    pub after: Option<Task>,
+    /// 通知したタスク(`ping_sent`)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tasks: Vec<Task>,
+}
+
+impl Payload {
+    pub fn new(event: EventKind, guild_id: GuildId) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            event,
+            guild_id,
+            datetime: Utc::now(),
+            before: None,
+            after: None,
+            tasks: vec![],
+        }
+    }
+
+    /// タスクの変更から作成します。
+    pub fn change(guild_id: GuildId, before: Option<Task>, after: Option<Task>) -> Self {
+        let event = match (&before, &after) {
+            (None, _) => EventKind::TaskCreated,
+            (Some(_), Some(_)) => EventKind::TaskEdited,
+            (Some(_), None) => EventKind::TaskDeleted,
+        };
+        Self {
+            before,
+            after,
+            ..Self::new(event, guild_id)
+        }
+    }
+}
+
+/// 1つのURLへの送信です。
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Delivery {
+    id: Uuid,
+    guild_id: GuildId,
+    url: String,
+    event: EventKind,
+    body: String,
+    attempts: u32,
+    next_attempt: DateTime<Utc>,
+}
+
+/// 送信待ちのWebhookです。
+pub struct Queue {
+    path: PathBuf,
+    deliveries: Mutex<Vec<Delivery>>,
+    notify: Notify,
+}
+
+impl Queue {
+    pub fn load() -> Result<Self, Error> {
+        let path = PathBuf::from(
+            std::env::var("WEBHOOK_QUEUE_PATH").unwrap_or("webhook_queue.json".into()),
+        );
+        let deliveries = match std::fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .with_context(|| format!("Failed to parse {}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => vec![],
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self {
+            path,
+            deliveries: Mutex::new(deliveries),
+            notify: Notify::new(),
+        })
+    }
+
+    fn save(&self, deliveries: &[Delivery]) -> Result<(), Error> {
+        storage::write_atomic(&self.path, serde_json::to_string(deliveries)?.as_bytes())
+    }
+
+    /// ギルドに登録されたすべてのURLへの送信を追加します。
+    pub fn push(&self, data: &Data, payload: &Payload) -> Result<(), Error> {
+        let urls = data
+            .guild(payload.guild_id)
+            .webhooks
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|webhook| webhook.url.clone())
+            .collect::<Vec<_>>();
+        if urls.is_empty() {
+            return Ok(());
+        }
+
+        let body = serde_json::to_string(payload)?;
+        {
+            let mut deliveries = self.deliveries.lock().unwrap();
+            deliveries.extend(urls.into_iter().map(|url| Delivery {
+                id: Uuid::new_v4(),
+                guild_id: payload.guild_id,
+                url,
+                event: payload.event,
+                body: body.clone(),
+                attempts: 0,
+                next_attempt: payload.datetime,
+            }));
+            self.save(&deliveries)?;
+        }
+        self.notify.notify_one();
+
+        Ok(())
+    }
+
+    /// 送信の結果を反映します。失敗した場合は次の送信日時を決めます。
+    fn finish(&self, id: Uuid, result: Result<(), Error>) -> Result<(), Error> {
+        let mut deliveries = self.deliveries.lock().unwrap();
+        let Some(i) = deliveries.iter().position(|delivery| delivery.id == id) else {
+            return Ok(());
+        };
+        match result {
+            Ok(()) => {
+                deliveries.remove(i);
+            }
+            Err(e) => {
+                let delivery = &mut deliveries[i];
+                delivery.attempts += 1;
+                println!(
+                    "[webhook] Failed to deliver {} to {} ({}/{}): {:?}",
+                    delivery.event.as_str(),
+                    delivery.url,
+                    delivery.attempts,
+                    MAX_ATTEMPTS,
+                    e
+                );
+                if MAX_ATTEMPTS <= delivery.attempts {
+                    println!("[webhook] Giving up {}", delivery.id);
+                    deliveries.remove(i);
+                } else {
+                    delivery.next_attempt = Utc::now() + backoff(delivery.attempts);
+                }
+            }
+        }
+        self.save(&deliveries)
+    }
+}
+
+/// `attempts`回失敗した後の待ち時間です。30秒から倍々に延ばします。
+fn backoff(attempts: u32) -> Duration {
+    Duration::seconds(30 << attempts.saturating_sub(1).min(20)).min(MAX_BACKOFF)
+}
+
+fn sign(secret: &str, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(body.as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+async fn send(client: &reqwest::Client, delivery: &Delivery, secret: &str) -> Result<(), Error> {
+    client
+        .post(&delivery.url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header("X-Task-Bot-Event", delivery.event.as_str())
+        .header("X-Task-Bot-Delivery", delivery.id.to_string())
+        .header("X-Task-Bot-Signature", sign(secret, &delivery.body))
+        .body(delivery.body.clone())
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+/// 送信待ちのWebhookを送り続けます。
+pub async fn run(data: Arc<Data>) {
+    let client = reqwest::Client::builder()
+        .timeout(std::time::Duration::from_secs(10))
+        .build()
+        .expect("Failed to build HTTP client");
+    let queue = &data.webhook_queue;
+
+    loop {
+        let now = Utc::now();
+        let due = queue
+            .deliveries
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|delivery| delivery.next_attempt <= now)
+            .cloned()
+            .collect::<Vec<_>>();
+
+        for delivery in due {
+            // 送信待ちの間に削除されたURLには送らない
+            let secret = data
+                .guild(delivery.guild_id)
+                .webhooks
+                .lock()
+                .unwrap()
+                .iter()
+                .find(|webhook| webhook.url == delivery.url)
+                .map(|webhook| webhook.secret.clone());
+            let result = match secret {
+                Some(secret) => send(&client, &delivery, &secret).await,
+                None => Ok(()),
+            };
+            if let Err(e) = queue.finish(delivery.id, result) {
+                println!("[webhook] Failed to save queue: {:?}", e);
+            }
+        }
+
+        let next_attempt = queue
+            .deliveries
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|delivery| delivery.next_attempt)
+            .min();
+        let wait = next_attempt
+            .map_or(MAX_BACKOFF, |next| next - Utc::now())
+            .to_std()
+            .unwrap_or_default();
+        let _ = timeout(wait, queue.notify.notified()).await;
+    }
+}